@@ -0,0 +1,310 @@
+//! Recognition of autolinks: CommonMark's `<https://...>` and `<user@host>`,
+//! and the GFM extended forms found in plain text when
+//! `ParserOptions::extended_autolinks` is enabled.
+
+/// An autolink found in the text, with the length of the source it spans.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Autolink {
+    /// A URL linked as written
+    Url(usize),
+    /// A `www.` address, linked with `http://` in front
+    Www(usize),
+    /// An email address, linked with `mailto:` in front
+    Email(usize),
+}
+
+impl Autolink {
+    /// The length of the autolink's text and what its destination adds in
+    /// front of it.
+    pub fn len_and_scheme(&self) -> (usize, &'static str) {
+        match *self {
+            Autolink::Url(len) => (len, ""),
+            Autolink::Www(len) => (len, "http://"),
+            Autolink::Email(len) => (len, "mailto:"),
+        }
+    }
+}
+
+/// Scans a CommonMark autolink starting at the `<` at `start`, returning the
+/// autolink with the length of its content between the angle brackets.
+pub fn scan_autolink(bytes: &[u8], start: usize) -> Option<Autolink> {
+    scan_url_autolink(bytes, start).or_else(|| scan_email_autolink(bytes, start))
+}
+
+fn scan_url_autolink(bytes: &[u8], start: usize) -> Option<Autolink> {
+    // Spaces, `<` and control characters can't be part of the URL, so the
+    // search ends at the first one rather than running on to a later `>`
+    let content = start + 1;
+    let len = bytes[content..]
+        .iter()
+        .position(|&b| b == b'>' || b <= b' ' || b == b'<' || b == 0x7F)?;
+    let end = content + len;
+    if bytes[end] != b'>' {
+        return None;
+    }
+    let inner = &bytes[content..end];
+
+    let scheme_len = inner.iter().position(|&b| b == b':')?;
+    let scheme = &inner[..scheme_len];
+    if (2..=32).contains(&scheme.len())
+        && scheme[0].is_ascii_alphabetic()
+        && scheme
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'.' | b'-'))
+    {
+        return Some(Autolink::Url(inner.len()));
+    }
+    None
+}
+
+/// Scans `<local@domain>`, returning its length between the angle brackets.
+fn scan_email_autolink(bytes: &[u8], start: usize) -> Option<Autolink> {
+    let content = start + 1;
+    let local_len = bytes[content..]
+        .iter()
+        .take_while(|&&b| b.is_ascii_alphanumeric() || b".!#$%&'*+/=?^_`{|}~-".contains(&b))
+        .count();
+    if local_len == 0 || bytes.get(content + local_len) != Some(&b'@') {
+        return None;
+    }
+
+    // Domain labels of up to 63 letters, digits or inner hyphens
+    let mut i = content + local_len + 1;
+    loop {
+        let label_len = bytes[i..]
+            .iter()
+            .take_while(|&&b| b.is_ascii_alphanumeric() || b == b'-')
+            .count();
+        let label = &bytes[i..i + label_len];
+        if label.is_empty() || label.len() > 63 || label[0] == b'-' || label[label_len - 1] == b'-'
+        {
+            return None;
+        }
+        i += label_len;
+        match bytes.get(i) {
+            Some(b'.') => i += 1,
+            Some(b'>') => return Some(Autolink::Email(i - content)),
+            _ => return None,
+        }
+    }
+}
+
+/// Scans a GFM extended URL autolink (`www.`, `http://` or `https://`) at
+/// `start`, which must not be preceded by a letter or digit.
+pub fn scan_extended_url(bytes: &[u8], start: usize) -> Option<Autolink> {
+    if start > 0 && !follows_boundary(bytes[start - 1]) {
+        return None;
+    }
+    let rest = &bytes[start..];
+    let (domain_start, www) = if starts_with_ignore_case(rest, b"www.") {
+        (start, true)
+    } else if starts_with_ignore_case(rest, b"https://") {
+        (start + 8, false)
+    } else if starts_with_ignore_case(rest, b"http://") {
+        (start + 7, false)
+    } else {
+        return None;
+    };
+
+    let domain_end = scan_domain(bytes, domain_start)?;
+    let end = bytes[domain_end..]
+        .iter()
+        .position(|&b| b.is_ascii_whitespace() || b == b'<')
+        .map_or(bytes.len(), |len| domain_end + len);
+    let end = trim_trailing_punctuation(&bytes[start..end]) + start;
+    if end <= domain_start {
+        return None;
+    }
+
+    let len = end - start;
+    Some(if www {
+        Autolink::Www(len)
+    } else {
+        Autolink::Url(len)
+    })
+}
+
+/// Scans a GFM extended email autolink whose `@` is at `at`, returning the
+/// start of its local part along with the autolink. The local part may not
+/// reach back before `min_start`.
+pub fn scan_extended_email(bytes: &[u8], min_start: usize, at: usize) -> Option<(usize, Autolink)> {
+    let local_len = bytes[min_start..at]
+        .iter()
+        .rev()
+        .take_while(|&&b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'+' | b'-' | b'_'))
+        .count();
+    let start = at - local_len;
+    if local_len == 0 {
+        return None;
+    }
+
+    let mut end = at + 1;
+    let mut periods = 0;
+    while let Some(&b) = bytes.get(end) {
+        match b {
+            b'.' if bytes.get(end + 1).is_some_and(|&b| is_domain_byte(b)) => periods += 1,
+            _ if is_domain_byte(b) => {}
+            _ => break,
+        }
+        end += 1;
+    }
+    if periods == 0 || matches!(bytes[end - 1], b'-' | b'_') {
+        return None;
+    }
+    Some((start, Autolink::Email(end - start)))
+}
+
+/// Extended autolinks start a word, or follow an opening delimiter.
+fn follows_boundary(b: u8) -> bool {
+    b.is_ascii_whitespace() || matches!(b, b'*' | b'_' | b'~' | b'(')
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn is_domain_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'-' || b == b'_'
+}
+
+/// Scans a domain of period-separated segments, at least two of them, where
+/// the last two contain no underscores. Returns where the domain ends.
+fn scan_domain(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut end = start;
+    let mut last_two = (start, start);
+    while let Some(&b) = bytes.get(end) {
+        match b {
+            b'.' if bytes.get(end + 1).is_some_and(|&b| is_domain_byte(b)) => {
+                last_two = (last_two.1, end + 1);
+            }
+            _ if is_domain_byte(b) || b >= 0x80 => {}
+            _ => break,
+        }
+        end += 1;
+    }
+
+    let has_period = last_two.1 > start;
+    (has_period && !bytes[last_two.0..end].contains(&b'_')).then_some(end)
+}
+
+/// Returns the length of `link` without the punctuation that more likely
+/// ends the surrounding sentence than the link: trailing `?!.,:*_~`,
+/// closing parentheses without a match in the link and entity-like `&name;`.
+fn trim_trailing_punctuation(link: &[u8]) -> usize {
+    let mut end = link.len();
+    loop {
+        match link[..end].last() {
+            Some(b'?' | b'!' | b'.' | b',' | b':' | b'*' | b'_' | b'~') => end -= 1,
+            Some(b')') => {
+                let opening = link[..end].iter().filter(|&&b| b == b'(').count();
+                let closing = link[..end].iter().filter(|&&b| b == b')').count();
+                if closing <= opening {
+                    return end;
+                }
+                end -= 1;
+            }
+            Some(b';') => {
+                let name_len = link[..end - 1]
+                    .iter()
+                    .rev()
+                    .take_while(|b| b.is_ascii_alphanumeric())
+                    .count();
+                let amp = end - 1 - name_len;
+                if name_len == 0 || amp == 0 || link[amp - 1] != b'&' {
+                    return end;
+                }
+                end = amp - 1;
+            }
+            _ => return end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_autolink() {
+        let scan = |s: &str| scan_autolink(s.as_bytes(), 0);
+        assert_eq!(scan("<https://example.com/a?b>x"), Some(Autolink::Url(23)));
+        assert_eq!(scan("<irc://foo.bar:2233/baz>"), Some(Autolink::Url(22)));
+        assert_eq!(scan("<a+b:c>"), Some(Autolink::Url(5)));
+        assert_eq!(scan("<m:abc>"), None);
+        assert_eq!(scan("<https://foo.bar/baz bim>"), None);
+        assert_eq!(scan("<foo.bar.baz>"), None);
+        assert_eq!(scan("<ab:c<ab:d>"), None);
+        assert_eq!(scan("<ab:c\n>"), None);
+
+        // An unclosed autolink at every `<` stays linear
+        let input = "<ab:".repeat(50_000) + ">";
+        let count = (0..input.len())
+            .step_by(4)
+            .filter(|&i| scan_autolink(input.as_bytes(), i).is_some())
+            .count();
+        assert_eq!(count, 1);
+
+        let email = |s: &str| scan_autolink(s.as_bytes(), 0);
+        assert_eq!(email("<foo@bar.example.com>"), Some(Autolink::Email(19)));
+        assert_eq!(
+            email("<foo+special@Bar.baz-bar0.com>"),
+            Some(Autolink::Email(28))
+        );
+        assert_eq!(email("<foo\\+@bar.example.com>"), None);
+        assert_eq!(email("<a@-b.com>"), None);
+    }
+
+    #[test]
+    fn test_scan_extended_url() {
+        let scan = |s: &'static str| match scan_extended_url(s.as_bytes(), 0) {
+            Some(Autolink::Url(len) | Autolink::Www(len)) => Some(&s[..len]),
+            _ => None,
+        };
+        assert_eq!(
+            scan("www.commonmark.org/help."),
+            Some("www.commonmark.org/help")
+        );
+        assert_eq!(
+            scan("https://example.com?q=1)."),
+            Some("https://example.com?q=1")
+        );
+        assert_eq!(
+            scan("www.google.com/search?q=(business))+ok"),
+            Some("www.google.com/search?q=(business))+ok")
+        );
+        assert_eq!(
+            scan("www.google.com/search?q=Markup+(business)))"),
+            Some("www.google.com/search?q=Markup+(business)")
+        );
+        assert_eq!(
+            scan("www.google.com/search?q=commonmark&hl;"),
+            Some("www.google.com/search?q=commonmark")
+        );
+        assert_eq!(
+            scan("www.commonmark.org/he<lp"),
+            Some("www.commonmark.org/he")
+        );
+        assert_eq!(scan("http://x_y.a.com"), Some("http://x_y.a.com"));
+        assert_eq!(scan("http://a_b.com"), None);
+        assert_eq!(scan("www.a.b_c"), None);
+        assert_eq!(scan("www."), None);
+        assert_eq!(scan("http://localhost"), None);
+        assert_eq!(scan_extended_url(b"xwww.a.com", 1), None);
+    }
+
+    #[test]
+    fn test_scan_extended_email() {
+        let scan = |s: &'static str| {
+            let at = s.find('@').unwrap();
+            match scan_extended_email(s.as_bytes(), 0, at) {
+                Some((start, Autolink::Email(len))) => Some(&s[start..start + len]),
+                _ => None,
+            }
+        };
+        assert_eq!(scan("mail foo@bar.baz."), Some("foo@bar.baz"));
+        assert_eq!(scan("a.b-c_d@a.b"), Some("a.b-c_d@a.b"));
+        assert_eq!(scan("a.b-c_d@a.b-"), None);
+        assert_eq!(scan("a@b"), None);
+        assert_eq!(scan("x @a.b"), None);
+    }
+}
@@ -0,0 +1,244 @@
+//! Emphasis and strong emphasis, resolved with the delimiter run algorithm of
+//! the CommonMark spec ("process emphasis").
+//!
+//! The optional `~~strikethrough~~`, `==mark==`, `^superscript^` and
+//! `~subscript~` go through the same algorithm, except that a closer only
+//! matches an opener of the same length and uses up the whole run.
+//!
+//! The inline scanner pushes each delimiter run as a placeholder text event
+//! and records it as a [`Delimiter`]. Once the whole text is scanned,
+//! [`resolve_emphasis`] matches closers to openers and replaces the
+//! placeholders with the emphasis tags and whatever is left of each run.
+
+use std::borrow::Cow;
+
+use crate::event::{Event, Tag};
+
+/// A run of delimiter characters found by the inline scanner.
+#[derive(Debug)]
+pub struct Delimiter {
+    /// Index of the placeholder event holding the run's text
+    event: usize,
+    /// Byte offset of the run in the scanned text
+    start: usize,
+    marker: u8,
+    /// Length of the whole run
+    len: usize,
+    /// Characters not yet used by a match
+    remaining: usize,
+    /// Characters used from the start of the run to close emphasis
+    closed: usize,
+    can_open: bool,
+    can_close: bool,
+    /// Cleared once the delimiter lies inside a match and can no longer open
+    /// or close anything
+    active: bool,
+    /// Tags ended by this run, innermost first
+    ends: Vec<Tag<'static>>,
+    /// Tags started by this run, innermost first
+    starts: Vec<Tag<'static>>,
+}
+
+impl Delimiter {
+    /// Length of the whole run
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Scans the run of `text[start]` characters, which is pushed as the event
+/// at index `event`, and classifies it with the flanking rules.
+pub fn scan_delimiter_run(text: &str, start: usize, event: usize) -> Delimiter {
+    let marker = text.as_bytes()[start];
+    let len = text.as_bytes()[start..]
+        .iter()
+        .take_while(|&&b| b == marker)
+        .count();
+
+    // The start and end of the text count as whitespace
+    let before = text[..start].chars().next_back().unwrap_or(' ');
+    let after = text[(start + len)..].chars().next().unwrap_or(' ');
+    let left_flanking = !after.is_whitespace()
+        && (!is_punctuation(after) || before.is_whitespace() || is_punctuation(before));
+    let right_flanking = !before.is_whitespace()
+        && (!is_punctuation(before) || after.is_whitespace() || is_punctuation(after));
+
+    // Underscores cannot open or close emphasis inside a word
+    let (can_open, can_close) = if marker == b'_' {
+        (
+            left_flanking && (!right_flanking || is_punctuation(before)),
+            right_flanking && (!left_flanking || is_punctuation(after)),
+        )
+    } else {
+        (left_flanking, right_flanking)
+    };
+
+    Delimiter {
+        event,
+        start,
+        marker,
+        len,
+        remaining: len,
+        closed: 0,
+        can_open,
+        can_close,
+        active: true,
+        ends: Vec::new(),
+        starts: Vec::new(),
+    }
+}
+
+/// The tag of an extension delimiter run, or `None` for `*` and `_`, which
+/// make emphasis or strong emphasis depending on how they pair up.
+fn extension_tag(marker: u8, len: usize) -> Option<Tag<'static>> {
+    match (marker, len) {
+        (b'~', 2) => Some(Tag::Strikethrough),
+        (b'~', _) => Some(Tag::Subscript),
+        (b'=', _) => Some(Tag::Mark),
+        (b'^', _) => Some(Tag::Superscript),
+        _ => None,
+    }
+}
+
+/// ASCII punctuation, and Unicode punctuation and symbols, approximated as
+/// any other character that is neither alphanumeric nor whitespace.
+fn is_punctuation(c: char) -> bool {
+    if c.is_ascii() {
+        c.is_ascii_punctuation()
+    } else {
+        !c.is_alphanumeric() && !c.is_whitespace()
+    }
+}
+
+/// Matches the delimiter runs up into emphasis and rewrites their placeholder
+/// events in `events`, which were scanned from `text`.
+pub fn resolve_emphasis<'t>(
+    text: &'t str,
+    events: &mut Vec<Event<'t>>,
+    mut delimiters: Vec<Delimiter>,
+) {
+    match_delimiters(&mut delimiters);
+    if delimiters
+        .iter()
+        .all(|delimiter| delimiter.remaining == delimiter.len)
+    {
+        return;
+    }
+
+    let first = delimiters[0].event;
+    let rest = events.split_off(first).into_iter().enumerate();
+    let mut delimiters = delimiters.into_iter().peekable();
+    for (offset, event) in rest {
+        let Some(delimiter) = delimiters.next_if(|d| d.event == first + offset) else {
+            events.push(event);
+            continue;
+        };
+
+        events.extend(delimiter.ends.into_iter().map(Event::End));
+        if delimiter.remaining > 0 {
+            let literal = delimiter.start + delimiter.closed;
+            events.push(Event::Text(Cow::Borrowed(
+                &text[literal..(literal + delimiter.remaining)],
+            )));
+        }
+        events.extend(delimiter.starts.into_iter().rev().map(Event::Start));
+    }
+}
+
+/// The matching loop of "process emphasis", over the whole delimiter stack.
+fn match_delimiters(delimiters: &mut [Delimiter]) {
+    // The lowest index worth searching for an opener, by marker, whether the
+    // closer can also open, and its length modulo 3
+    let mut openers_bottom: Vec<((u8, bool, usize), usize)> = Vec::new();
+
+    let mut closer = 0;
+    while closer < delimiters.len() {
+        let c = &delimiters[closer];
+        if !c.can_close || !c.active || c.remaining == 0 {
+            closer += 1;
+            continue;
+        }
+
+        let extension = extension_tag(c.marker, c.len);
+        let key = (c.marker, c.can_open, c.len % 3);
+        let bottom = openers_bottom
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map_or(0, |&(_, bottom)| bottom);
+        let opener = (bottom..closer).rev().find(|&i| {
+            let o = &delimiters[i];
+            o.active
+                && o.can_open
+                && o.remaining > 0
+                && o.marker == c.marker
+                && if extension.is_some() {
+                    o.len == c.len
+                } else {
+                    // Rule of 3: a run that can both open and close only
+                    // pairs with one whose combined length is not a multiple
+                    // of 3, unless both lengths are
+                    !((o.can_close || c.can_open)
+                        && (o.len + c.len).is_multiple_of(3)
+                        && !(o.len.is_multiple_of(3) && c.len.is_multiple_of(3)))
+                }
+        });
+
+        let Some(opener) = opener else {
+            match openers_bottom.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, bottom)) => *bottom = closer,
+                None => openers_bottom.push((key, closer)),
+            }
+            if !delimiters[closer].can_open {
+                delimiters[closer].active = false;
+            }
+            closer += 1;
+            continue;
+        };
+
+        let strong = delimiters[opener].remaining >= 2 && delimiters[closer].remaining >= 2;
+        let (used, tag) = match extension {
+            Some(tag) => (delimiters[closer].len, tag),
+            None if strong => (2, Tag::Strong),
+            None => (1, Tag::Emphasis),
+        };
+
+        let o = &mut delimiters[opener];
+        o.remaining -= used;
+        o.starts.push(tag.clone());
+        let c = &mut delimiters[closer];
+        c.remaining -= used;
+        c.closed += used;
+        c.ends.push(tag);
+
+        // Delimiters between the pair can no longer match
+        for between in &mut delimiters[(opener + 1)..closer] {
+            between.active = false;
+        }
+        if delimiters[closer].remaining == 0 {
+            closer += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flanking(text: &str, start: usize) -> (bool, bool) {
+        let delimiter = scan_delimiter_run(text, start, 0);
+        (delimiter.can_open, delimiter.can_close)
+    }
+
+    #[test]
+    fn test_flanking_rules() {
+        assert_eq!(flanking("*a", 0), (true, false));
+        assert_eq!(flanking("a*", 1), (false, true));
+        assert_eq!(flanking("a*b", 1), (true, true));
+        assert_eq!(flanking("a * b", 2), (false, false));
+        assert_eq!(flanking("a _b", 2), (true, false));
+        assert_eq!(flanking("snake_case", 5), (false, false));
+        assert_eq!(flanking("\"_a", 1), (true, false));
+        assert_eq!(flanking("a**\"b\"", 1), (false, true));
+        assert_eq!(flanking("foo-_(bar)_", 4), (true, true));
+    }
+}
@@ -0,0 +1,192 @@
+use std::borrow::Cow;
+
+/// A single step of the parsed document, produced by [`MarkdownParser::events`].
+///
+/// Container elements are bracketed by a `Start`/`End` pair carrying the same
+/// [`Tag`]; everything between them is the element's content.
+///
+/// [`MarkdownParser::events`]: crate::MarkdownParser::events
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    Start(Tag<'a>),
+    End(Tag<'a>),
+    /// Plain text, not yet HTML-escaped
+    Text(Cow<'a, str>),
+    /// Inline code span content
+    Code(Cow<'a, str>),
+    /// A raw HTML block, passed through when raw HTML is enabled
+    Html(Cow<'a, str>),
+    /// A raw inline HTML tag, passed through when raw HTML is enabled
+    InlineHtml(Cow<'a, str>),
+    /// A reference to a footnote, `[^label]`. Footnotes are numbered from 1
+    /// in the order of their first reference, and `occurrence` counts the
+    /// references to the same footnote so far, also from 1.
+    FootnoteReference {
+        label: Cow<'a, str>,
+        number: usize,
+        occurrence: usize,
+    },
+    /// A line ending inside a paragraph
+    SoftBreak,
+    /// A line ending forced with two trailing spaces or a backslash
+    HardBreak,
+    /// A thematic break (`---`, `***` or `___`)
+    Rule,
+}
+
+/// An element that has content, opened by [`Event::Start`] and closed by
+/// [`Event::End`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag<'a> {
+    Paragraph,
+    /// Heading with its level, 1 to 6
+    Heading(usize),
+    BlockQuote,
+    /// A code block with its info string, e.g. `rust` for a fence opened by
+    /// ```` ```rust ````, or empty if there is none
+    CodeBlock(Cow<'a, str>),
+    /// A list, with the start number for ordered lists and `None` for bullets.
+    ///
+    /// Items of a tight list contain their inline content directly rather than
+    /// wrapped in paragraphs.
+    List(Option<u64>),
+    /// A list item. Task list items such as `- [x] done` carry whether their
+    /// box is checked, and `None` is a plain item.
+    Item(Option<bool>),
+    /// A table with the alignment of each column
+    Table(Vec<Alignment>),
+    /// The header row of a table, containing its cells directly
+    TableHead,
+    TableRow,
+    TableCell,
+    Emphasis,
+    Strong,
+    /// `~~text~~`, with [`ParserOptions::strikethrough`]
+    ///
+    /// [`ParserOptions::strikethrough`]: crate::ParserOptions::strikethrough
+    Strikethrough,
+    /// `==text==`, with [`ParserOptions::mark`]
+    ///
+    /// [`ParserOptions::mark`]: crate::ParserOptions::mark
+    Mark,
+    /// `^text^`, with [`ParserOptions::superscript`]
+    ///
+    /// [`ParserOptions::superscript`]: crate::ParserOptions::superscript
+    Superscript,
+    /// `~text~`, with [`ParserOptions::subscript`]
+    ///
+    /// [`ParserOptions::subscript`]: crate::ParserOptions::subscript
+    Subscript,
+    /// A link. `title` is empty if the link has none.
+    Link {
+        dest_url: Cow<'a, str>,
+        title: Cow<'a, str>,
+    },
+    /// An image, whose content is its alt text. `title` is empty if the image
+    /// has none.
+    Image {
+        dest_url: Cow<'a, str>,
+        title: Cow<'a, str>,
+    },
+    /// The referenced footnotes, after the last block of the document
+    Footnotes,
+    /// The content of a footnote, with its number and how many references
+    /// to it there are
+    FootnoteDefinition {
+        number: usize,
+        references: usize,
+    },
+}
+
+/// The target of reference links such as `[text][label]`, defined in the
+/// document by `[label]: /url "title"` or supplied with
+/// [`MarkdownParser::with_link_definition`].
+///
+/// [`MarkdownParser::with_link_definition`]: crate::MarkdownParser::with_link_definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkDefinition<'a> {
+    pub dest_url: Cow<'a, str>,
+    /// Empty if the definition has no title
+    pub title: Cow<'a, str>,
+}
+
+/// Column alignment of a table, taken from the colons of its delimiter row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+impl Event<'_> {
+    /// Detaches the event from the input it borrows from.
+    pub fn into_owned(self) -> Event<'static> {
+        match self {
+            Event::Start(tag) => Event::Start(tag.into_owned()),
+            Event::End(tag) => Event::End(tag.into_owned()),
+            Event::Text(text) => Event::Text(Cow::Owned(text.into_owned())),
+            Event::Code(code) => Event::Code(Cow::Owned(code.into_owned())),
+            Event::Html(html) => Event::Html(Cow::Owned(html.into_owned())),
+            Event::InlineHtml(html) => Event::InlineHtml(Cow::Owned(html.into_owned())),
+            Event::FootnoteReference {
+                label,
+                number,
+                occurrence,
+            } => Event::FootnoteReference {
+                label: Cow::Owned(label.into_owned()),
+                number,
+                occurrence,
+            },
+            Event::SoftBreak => Event::SoftBreak,
+            Event::HardBreak => Event::HardBreak,
+            Event::Rule => Event::Rule,
+        }
+    }
+}
+
+impl Tag<'_> {
+    /// Detaches the tag from the input it borrows from.
+    pub fn into_owned(self) -> Tag<'static> {
+        match self {
+            Tag::Paragraph => Tag::Paragraph,
+            Tag::Heading(level) => Tag::Heading(level),
+            Tag::BlockQuote => Tag::BlockQuote,
+            Tag::CodeBlock(info) => Tag::CodeBlock(Cow::Owned(info.into_owned())),
+            Tag::List(start) => Tag::List(start),
+            Tag::Item(task) => Tag::Item(task),
+            Tag::Table(alignments) => Tag::Table(alignments),
+            Tag::TableHead => Tag::TableHead,
+            Tag::TableRow => Tag::TableRow,
+            Tag::TableCell => Tag::TableCell,
+            Tag::Emphasis => Tag::Emphasis,
+            Tag::Strong => Tag::Strong,
+            Tag::Strikethrough => Tag::Strikethrough,
+            Tag::Mark => Tag::Mark,
+            Tag::Superscript => Tag::Superscript,
+            Tag::Subscript => Tag::Subscript,
+            Tag::Link { dest_url, title } => Tag::Link {
+                dest_url: Cow::Owned(dest_url.into_owned()),
+                title: Cow::Owned(title.into_owned()),
+            },
+            Tag::Image { dest_url, title } => Tag::Image {
+                dest_url: Cow::Owned(dest_url.into_owned()),
+                title: Cow::Owned(title.into_owned()),
+            },
+            Tag::Footnotes => Tag::Footnotes,
+            Tag::FootnoteDefinition { number, references } => {
+                Tag::FootnoteDefinition { number, references }
+            }
+        }
+    }
+}
+
+impl LinkDefinition<'_> {
+    /// Detaches the definition from the input it borrows from.
+    pub fn into_owned(self) -> LinkDefinition<'static> {
+        LinkDefinition {
+            dest_url: Cow::Owned(self.dest_url.into_owned()),
+            title: Cow::Owned(self.title.into_owned()),
+        }
+    }
+}
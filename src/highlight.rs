@@ -0,0 +1,695 @@
+//! Offline syntax highlighting for fenced code blocks, compiled in with the
+//! `highlight` feature.
+//!
+//! Each supported language is described by a small [`Syntax`] table driving
+//! one shared scanner, which is far from a full lexer but gets keywords,
+//! strings, comments and numbers right for typical documentation snippets.
+//! Tokens are wrapped in `<span class="tok-...">`, leaving the colours to the
+//! page's stylesheet.
+
+use crate::simd::html_escape_simd_into;
+
+/// The kinds of token that get a `tok-` class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Keyword,
+    Type,
+    Literal,
+    Number,
+    String,
+    Comment,
+    /// Keys of TOML, YAML and JSON entries
+    Key,
+    /// Shell variables such as `$HOME`
+    Variable,
+    /// Section headers, decorators and diff metadata
+    Meta,
+    /// Added lines of a diff
+    Inserted,
+    /// Removed lines of a diff
+    Deleted,
+}
+
+impl Token {
+    fn class(self) -> &'static str {
+        match self {
+            Token::Keyword => "tok-keyword",
+            Token::Type => "tok-type",
+            Token::Literal => "tok-literal",
+            Token::Number => "tok-number",
+            Token::String => "tok-string",
+            Token::Comment => "tok-comment",
+            Token::Key => "tok-key",
+            Token::Variable => "tok-variable",
+            Token::Meta => "tok-meta",
+            Token::Inserted => "tok-inserted",
+            Token::Deleted => "tok-deleted",
+        }
+    }
+}
+
+/// What the scanner needs to know about a language.
+struct Syntax {
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    literals: &'static [&'static str],
+    /// Starts a comment running to the end of the line. A `#` only does so at
+    /// the start of a word, so `a#b` in a shell command stays plain text.
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [u8],
+    multiline_strings: bool,
+    /// Separator after the key of an entry (`=` in TOML, `:` in YAML), for
+    /// marking bare keys at the start of a line and quoted keys
+    key_separator: Option<u8>,
+    /// Identifiers starting with a capital letter are types
+    capitalized_types: bool,
+    /// Rust's `'c'` character literals next to `'a` lifetimes, and raw strings
+    rust_literals: bool,
+    /// Python's `"""` strings
+    triple_quotes: bool,
+    /// Shell `$variables`, and single-quoted strings without escapes
+    shell: bool,
+    /// `[section]` lines in TOML, `@decorators` in Python
+    meta_prefix: Option<u8>,
+}
+
+const PLAIN: Syntax = Syntax {
+    keywords: &[],
+    types: &[],
+    literals: &[],
+    line_comment: None,
+    block_comment: None,
+    quotes: &[],
+    multiline_strings: false,
+    key_separator: None,
+    capitalized_types: false,
+    rust_literals: false,
+    triple_quotes: false,
+    shell: false,
+    meta_prefix: None,
+};
+
+const RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "static", "struct", "super", "trait", "type", "unsafe",
+        "use", "where", "while",
+    ],
+    types: &[
+        "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+        "i64", "i128", "isize", "f32", "f64",
+    ],
+    literals: &["true", "false"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: b"\"",
+    multiline_strings: true,
+    capitalized_types: true,
+    rust_literals: true,
+    ..PLAIN
+};
+
+const TOML: Syntax = Syntax {
+    literals: &["true", "false", "inf", "nan"],
+    line_comment: Some("#"),
+    quotes: b"\"'",
+    key_separator: Some(b'='),
+    meta_prefix: Some(b'['),
+    ..PLAIN
+};
+
+const JSON: Syntax = Syntax {
+    literals: &["true", "false", "null"],
+    quotes: b"\"",
+    key_separator: Some(b':'),
+    ..PLAIN
+};
+
+const SHELL: Syntax = Syntax {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "function", "return", "export", "local", "readonly", "source", "set", "unset",
+    ],
+    line_comment: Some("#"),
+    quotes: b"\"'",
+    multiline_strings: true,
+    shell: true,
+    ..PLAIN
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+        "yield",
+    ],
+    literals: &["True", "False", "None"],
+    line_comment: Some("#"),
+    quotes: b"\"'",
+    triple_quotes: true,
+    meta_prefix: Some(b'@'),
+    ..PLAIN
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "debugger",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "let",
+        "new",
+        "of",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "try",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "with",
+        "yield",
+    ],
+    literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: b"\"'`",
+    ..PLAIN
+};
+
+const YAML: Syntax = Syntax {
+    literals: &["true", "false", "null", "yes", "no", "on", "off"],
+    line_comment: Some("#"),
+    quotes: b"\"'",
+    key_separator: Some(b':'),
+    ..PLAIN
+};
+
+/// Finds the syntax for the language named by a fence's info string.
+fn syntax_for(language: &str) -> Option<&'static Syntax> {
+    let syntax = match language.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => &RUST,
+        "toml" => &TOML,
+        "json" | "jsonc" => &JSON,
+        "sh" | "bash" | "shell" | "zsh" | "console" => &SHELL,
+        "python" | "py" => &PYTHON,
+        "javascript" | "js" | "mjs" | "cjs" | "jsx" => &JAVASCRIPT,
+        "yaml" | "yml" => &YAML,
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+/// Appends `code` to `out` with its tokens wrapped in `<span class="tok-...">`
+/// and everything HTML-escaped. Returns false, writing nothing, if `language`
+/// isn't one of the supported ones (Rust, TOML, JSON, shell, Python,
+/// JavaScript, YAML and diff, under their usual names and extensions).
+///
+/// ```
+/// let mut html = String::new();
+/// assert!(md2html::highlight_into("rust", "let x = 1;", &mut html));
+/// assert_eq!(
+///     html,
+///     "<span class=\"tok-keyword\">let</span> x = <span class=\"tok-number\">1</span>;"
+/// );
+/// ```
+pub fn highlight_into(language: &str, code: &str, out: &mut String) -> bool {
+    if matches!(language.to_ascii_lowercase().as_str(), "diff" | "patch") {
+        highlight_diff(code, out);
+        return true;
+    }
+    let Some(syntax) = syntax_for(language) else {
+        return false;
+    };
+    Scanner {
+        syntax,
+        code,
+        bytes: code.as_bytes(),
+        out,
+        pos: 0,
+        plain_start: 0,
+        line: LineSoFar::Blank,
+    }
+    .run();
+    true
+}
+
+fn push_token(out: &mut String, token: Token, text: &str) {
+    out.push_str("<span class=\"");
+    out.push_str(token.class());
+    out.push_str("\">");
+    html_escape_simd_into(text, out);
+    out.push_str("</span>");
+}
+
+fn highlight_diff(code: &str, out: &mut String) {
+    for line in code.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let token = if ["+++", "---", "diff ", "index ", "@@"]
+            .iter()
+            .any(|prefix| content.starts_with(prefix))
+        {
+            Some(Token::Meta)
+        } else if content.starts_with('+') {
+            Some(Token::Inserted)
+        } else if content.starts_with('-') {
+            Some(Token::Deleted)
+        } else {
+            None
+        };
+        match token {
+            Some(token) => push_token(out, token, content),
+            None => html_escape_simd_into(content, out),
+        }
+        out.push_str(&line[content.len()..]);
+    }
+}
+
+struct Scanner<'s, 'o> {
+    syntax: &'static Syntax,
+    code: &'s str,
+    bytes: &'s [u8],
+    out: &'o mut String,
+    pos: usize,
+    /// Start of the plain text not yet written out
+    plain_start: usize,
+    /// What the line holds before `pos`
+    line: LineSoFar,
+}
+
+/// What the current line holds so far, updated as the scanner advances so
+/// that recognising a token never looks back over the line.
+#[derive(Clone, Copy, PartialEq)]
+enum LineSoFar {
+    Blank,
+    /// A `-` after the indentation
+    Dash,
+    /// A `-` followed by whitespace, where a YAML sequence item starts
+    DashSpace,
+    Other,
+}
+
+impl Scanner<'_, '_> {
+    fn run(&mut self) {
+        while self.pos < self.bytes.len() {
+            match self.token_at(self.pos) {
+                Some((token, end)) => {
+                    html_escape_simd_into(&self.code[self.plain_start..self.pos], self.out);
+                    push_token(self.out, token, &self.code[self.pos..end]);
+                    self.advance_to(end);
+                    self.plain_start = end;
+                }
+                // Step over whole characters, so slices stay on char boundaries
+                None => {
+                    let char_len = self.code[self.pos..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8);
+                    self.advance_to(self.pos + self.word_len(self.pos).max(char_len));
+                }
+            }
+        }
+        html_escape_simd_into(&self.code[self.plain_start..], self.out);
+    }
+
+    /// Moves `pos` to `end`, keeping track of what the line holds.
+    fn advance_to(&mut self, end: usize) {
+        for &byte in &self.bytes[self.pos..end] {
+            self.line = match (byte, self.line) {
+                (b'\n', _) => LineSoFar::Blank,
+                (b'-', LineSoFar::Blank) => LineSoFar::Dash,
+                (b, LineSoFar::Dash | LineSoFar::DashSpace) if b.is_ascii_whitespace() => {
+                    LineSoFar::DashSpace
+                }
+                (b, line) if b.is_ascii_whitespace() => line,
+                _ => LineSoFar::Other,
+            };
+        }
+        self.pos = end;
+    }
+
+    /// Recognises a token starting at `pos`, returning it and its end.
+    fn token_at(&self, pos: usize) -> Option<(Token, usize)> {
+        let syntax = self.syntax;
+        let bytes = self.bytes;
+        let rest = &self.code[pos..];
+        let byte = bytes[pos];
+        let at_line_start = self.line == LineSoFar::Blank;
+        let after_space = pos == 0 || bytes[pos - 1].is_ascii_whitespace();
+
+        if let Some(marker) = syntax.line_comment {
+            if rest.starts_with(marker) && (marker != "#" || after_space) {
+                return Some((Token::Comment, self.line_end(pos)));
+            }
+        }
+        if let Some((open, close)) = syntax.block_comment {
+            if let Some(body) = rest.strip_prefix(open) {
+                let end = body
+                    .find(close)
+                    .map_or(bytes.len(), |i| pos + open.len() + i + close.len());
+                return Some((Token::Comment, end));
+            }
+        }
+        if syntax.meta_prefix == Some(byte) && at_line_start {
+            return Some((Token::Meta, self.line_end(pos)));
+        }
+        if syntax.shell && byte == b'$' {
+            return self.variable(pos).map(|end| (Token::Variable, end));
+        }
+        if syntax.rust_literals {
+            if let Some(end) = self.rust_literal(pos) {
+                return Some((Token::String, end));
+            }
+        }
+        if syntax.quotes.contains(&byte) {
+            let end = self.string_end(pos);
+            let token = if self.separator_follows(end, true) {
+                Token::Key
+            } else {
+                Token::String
+            };
+            return Some((token, end));
+        }
+        if byte.is_ascii_digit() {
+            return Some((Token::Number, self.number_end(pos)));
+        }
+
+        let len = self.word_len(pos);
+        if len == 0 {
+            return None;
+        }
+        let word = &rest[..len];
+        let end = pos + len;
+        let key_start = at_line_start
+            || (syntax.key_separator == Some(b':') && self.line == LineSoFar::DashSpace);
+        if key_start && syntax.key_separator.is_some() {
+            if let Some(key_end) = self.bare_key_end(pos) {
+                return Some((Token::Key, key_end));
+            }
+        }
+        if syntax.keywords.contains(&word) {
+            Some((Token::Keyword, end))
+        } else if syntax.literals.contains(&word) {
+            Some((Token::Literal, end))
+        } else if syntax.types.contains(&word)
+            || (syntax.capitalized_types && word.as_bytes()[0].is_ascii_uppercase())
+        {
+            Some((Token::Type, end))
+        } else {
+            None
+        }
+    }
+
+    /// Length of the identifier starting at `pos`, or 0.
+    fn word_len(&self, pos: usize) -> usize {
+        let bytes = &self.bytes[pos..];
+        if !bytes
+            .first()
+            .is_some_and(|b| b.is_ascii_alphabetic() || *b == b'_')
+        {
+            return 0;
+        }
+        bytes
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+            .count()
+    }
+
+    fn line_end(&self, pos: usize) -> usize {
+        self.code[pos..]
+            .find('\n')
+            .map_or(self.bytes.len(), |i| pos + i)
+    }
+
+    /// The end of a bare key such as `name` or `package.version` at `pos`, if
+    /// the key separator follows it.
+    fn bare_key_end(&self, pos: usize) -> Option<usize> {
+        let len = self.bytes[pos..]
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.'))
+            .count();
+        let end = pos + len;
+        self.separator_follows(end, false).then_some(end)
+    }
+
+    /// True if the key separator follows `end`. After a bare key, a colon
+    /// must be followed by whitespace as in YAML, so `http://x` is no key.
+    fn separator_follows(&self, end: usize, quoted: bool) -> bool {
+        let Some(separator) = self.syntax.key_separator else {
+            return false;
+        };
+        let after = self.code[end..].trim_start_matches([' ', '\t']).as_bytes();
+        after.first() == Some(&separator)
+            && (quoted || separator != b':' || after.get(1).is_none_or(u8::is_ascii_whitespace))
+    }
+
+    fn string_end(&self, pos: usize) -> usize {
+        let bytes = self.bytes;
+        let quote = bytes[pos];
+        if self.syntax.triple_quotes && bytes[pos..].starts_with(&[quote; 3]) {
+            let close = [quote; 3];
+            return bytes[pos + 3..]
+                .windows(3)
+                .position(|window| window == close)
+                .map_or(bytes.len(), |i| pos + 3 + i + 3);
+        }
+
+        let escapes = !(self.syntax.shell && quote == b'\'');
+        let multiline = self.syntax.multiline_strings || quote == b'`';
+        let mut i = pos + 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if escapes => i += 1,
+                b'\n' if !multiline => return i,
+                b if b == quote => return i + 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        bytes.len()
+    }
+
+    fn number_end(&self, pos: usize) -> usize {
+        let bytes = self.bytes;
+        let mut i = pos;
+        while i < bytes.len()
+            && (bytes[i].is_ascii_alphanumeric()
+                || bytes[i] == b'_'
+                || (bytes[i] == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)))
+        {
+            i += 1;
+        }
+        i
+    }
+
+    /// `$name`, `${...}` and special parameters such as `$?` or `$1`.
+    fn variable(&self, pos: usize) -> Option<usize> {
+        let next = *self.bytes.get(pos + 1)?;
+        // Like a string, an unclosed `${` runs to the end of the line
+        if next == b'{' {
+            let end = self.line_end(pos);
+            return Some(self.code[pos..end].find('}').map_or(end, |i| pos + i + 1));
+        }
+        if matches!(next, b'?' | b'@' | b'#' | b'*' | b'$' | b'!' | b'-') || next.is_ascii_digit() {
+            return Some(pos + 2);
+        }
+        let len = self.word_len(pos + 1);
+        (len > 0).then_some(pos + 1 + len)
+    }
+
+    /// Rust character literals (`'a'`, `'\n'`) and raw strings (`r#"..."#`).
+    /// A quote that starts neither is a lifetime and stays plain text.
+    fn rust_literal(&self, pos: usize) -> Option<usize> {
+        let rest = &self.code[pos..];
+        if let Some(after_r) = rest.strip_prefix('r').or_else(|| rest.strip_prefix("br")) {
+            let prefix = rest.len() - after_r.len();
+            let hashes = after_r.bytes().take_while(|&b| b == b'#').count();
+            if after_r.as_bytes().get(hashes) != Some(&b'"') {
+                return None;
+            }
+            // A word ending in `r` isn't a raw string prefix
+            if pos > 0
+                && (self.bytes[pos - 1].is_ascii_alphanumeric() || self.bytes[pos - 1] == b'_')
+            {
+                return None;
+            }
+            let close = format!("\"{}", "#".repeat(hashes));
+            let body = pos + prefix + hashes + 1;
+            return Some(
+                self.code[body..]
+                    .find(&close)
+                    .map_or(self.bytes.len(), |i| body + i + close.len()),
+            );
+        }
+
+        if !rest.starts_with('\'') {
+            return None;
+        }
+        let mut chars = rest[1..].char_indices();
+        let len = match chars.next()? {
+            // Skip the escaped character, which may itself be a quote
+            (_, '\\') => rest.get(3..)?.find('\'')? + 4,
+            _ => match chars.next()? {
+                (i, '\'') => i + 2,
+                _ => return None,
+            },
+        };
+        Some(pos + len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlight(language: &str, code: &str) -> String {
+        let mut out = String::new();
+        assert!(highlight_into(language, code, &mut out));
+        out
+    }
+
+    #[test]
+    fn test_rust() {
+        assert_eq!(
+            highlight("rust", "fn f<'a>(c: char) -> Option<&'a str> { 'x' } // done"),
+            "<span class=\"tok-keyword\">fn</span> f&lt;&#39;a&gt;(c: <span class=\"tok-type\">char</span>) -&gt; \
+             <span class=\"tok-type\">Option</span>&lt;&amp;&#39;a <span class=\"tok-type\">str</span>&gt; \
+             { <span class=\"tok-string\">&#39;x&#39;</span> } <span class=\"tok-comment\">// done</span>"
+        );
+        assert_eq!(
+            highlight("rust", "['\\'', '\\n', 'é']"),
+            "[<span class=\"tok-string\">&#39;\\&#39;&#39;</span>, <span class=\"tok-string\">&#39;\\n&#39;</span>, \
+             <span class=\"tok-string\">&#39;é&#39;</span>]"
+        );
+        assert_eq!(
+            highlight("rs", "r#\"a \"quoted\" b\"# 0x1F"),
+            "<span class=\"tok-string\">r#&quot;a &quot;quoted&quot; b&quot;#</span> \
+             <span class=\"tok-number\">0x1F</span>"
+        );
+    }
+
+    #[test]
+    fn test_config_keys() {
+        assert_eq!(
+            highlight("toml", "[package]\nname = \"md2html\" # crate\nedition = 2021"),
+            "<span class=\"tok-meta\">[package]</span>\n<span class=\"tok-key\">name</span> = \
+             <span class=\"tok-string\">&quot;md2html&quot;</span> <span class=\"tok-comment\"># crate</span>\n\
+             <span class=\"tok-key\">edition</span> = <span class=\"tok-number\">2021</span>"
+        );
+        assert_eq!(
+            highlight("yaml", "- url: http://x\n  on: true"),
+            "- <span class=\"tok-key\">url</span>: http://x\n  <span class=\"tok-key\">on</span>: \
+             <span class=\"tok-literal\">true</span>"
+        );
+        assert_eq!(
+            highlight("json", "{\"a\": [1.5, null]}"),
+            "{<span class=\"tok-key\">&quot;a&quot;</span>: [<span class=\"tok-number\">1.5</span>, \
+             <span class=\"tok-literal\">null</span>]}"
+        );
+    }
+
+    #[test]
+    fn test_shell_python_javascript() {
+        assert_eq!(
+            highlight("sh", "echo \"$HOME\" '$x' a#b # note"),
+            "echo <span class=\"tok-string\">&quot;$HOME&quot;</span> <span class=\"tok-string\">&#39;$x&#39;</span> \
+             a#b <span class=\"tok-comment\"># note</span>"
+        );
+        assert_eq!(
+            highlight("bash", "if [ -n $1 ]; then"),
+            "<span class=\"tok-keyword\">if</span> [ -n <span class=\"tok-variable\">$1</span> ]; \
+             <span class=\"tok-keyword\">then</span>"
+        );
+        assert_eq!(
+            highlight("python", "@cache\ndef f():\n    \"\"\"Doc\n    string\"\"\"\n    return None"),
+            "<span class=\"tok-meta\">@cache</span>\n<span class=\"tok-keyword\">def</span> f():\n    \
+             <span class=\"tok-string\">&quot;&quot;&quot;Doc\n    string&quot;&quot;&quot;</span>\n    \
+             <span class=\"tok-keyword\">return</span> <span class=\"tok-literal\">None</span>"
+        );
+        assert_eq!(
+            highlight("js", "const s = `a ${b}`; /* c */"),
+            "<span class=\"tok-keyword\">const</span> s = <span class=\"tok-string\">`a ${b}`</span>; \
+             <span class=\"tok-comment\">/* c */</span>"
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(
+            highlight("diff", "@@ -1 +1 @@\n-old\n+new\n same\n"),
+            "<span class=\"tok-meta\">@@ -1 +1 @@</span>\n<span class=\"tok-deleted\">-old</span>\n\
+             <span class=\"tok-inserted\">+new</span>\n same\n"
+        );
+    }
+
+    #[test]
+    fn test_unknown_language_and_non_ascii() {
+        let mut out = String::new();
+        assert!(!highlight_into("brainfuck", "+++", &mut out));
+        assert!(out.is_empty());
+
+        assert_eq!(
+            highlight("python", "s = 'héllo' # ünïcode"),
+            "s = <span class=\"tok-string\">&#39;héllo&#39;</span> <span class=\"tok-comment\"># ünïcode</span>"
+        );
+
+        // Non-ASCII identifiers and operators outside strings are plain text
+        assert_eq!(highlight("python", "print(café)"), "print(café)");
+        assert_eq!(
+            highlight("rust", "let x = a → b;"),
+            "<span class=\"tok-keyword\">let</span> x = a → b;"
+        );
+        assert_eq!(
+            highlight("json", "\"\"é"),
+            "<span class=\"tok-string\">&quot;&quot;</span>é"
+        );
+        assert_eq!(highlight("sh", "$é 日本"), "$é 日本");
+    }
+
+    #[test]
+    fn test_long_single_line() {
+        let json = format!(
+            "[{}]",
+            "{\"key\": [1, true], \"x\": \"y\"}, ".repeat(20_000)
+        );
+        let out = highlight("json", &json);
+        assert_eq!(out.matches("tok-key").count(), 40_000);
+        assert_eq!(out.matches("tok-number").count(), 20_000);
+
+        let yaml = format!("- {}key: 1", "- ".repeat(50_000));
+        assert!(!highlight("yaml", &yaml).contains("tok-key"));
+
+        assert_eq!(
+            highlight("sh", "echo ${a ${b\n${c}"),
+            "echo <span class=\"tok-variable\">${a ${b</span>\n<span class=\"tok-variable\">${c}</span>"
+        );
+        let out = highlight("sh", &"${".repeat(100_000));
+        assert_eq!(out.matches("tok-variable").count(), 1);
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use crate::event::Event;
+use crate::options::{ParserOptions, SoftBreakStyle};
+use crate::render::{render, Renderer};
+
+/// Renders an event stream as HTML, appending to `output`.
+///
+/// This is what [`MarkdownParser::parse`] uses, so events taken from
+/// [`MarkdownParser::events`] and transformed along the way render exactly
+/// like the untouched document.
+///
+/// [`MarkdownParser::parse`]: crate::MarkdownParser::parse
+/// [`MarkdownParser::events`]: crate::MarkdownParser::events
+pub fn push_html<'a, I>(output: &mut String, events: I)
+where
+    I: IntoIterator<Item = Event<'a>>,
+{
+    render(&mut HtmlRenderer::new(), output, events);
+}
+
+/// The default [`Renderer`], producing md2html's standard HTML.
+///
+/// Custom renderers embed one and return it from
+/// [`Renderer::html_renderer`] to keep the standard output for every element
+/// they do not override.
+#[derive(Debug, Clone)]
+pub struct HtmlRenderer {
+    pub(crate) soft_break: SoftBreakStyle,
+    pub(crate) list_indent: usize,
+    pub(crate) xhtml: bool,
+    pub(crate) table_alignment_class: Option<String>,
+    pub(crate) lazy_images: bool,
+    pub(crate) image_src_prefix: Option<String>,
+    pub(crate) image_size_root: Option<PathBuf>,
+    pub(crate) in_table_body: bool,
+}
+
+impl Default for HtmlRenderer {
+    fn default() -> Self {
+        Self::with_options(&ParserOptions::default())
+    }
+}
+
+impl HtmlRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a renderer following the output settings of `options`.
+    pub fn with_options(options: &ParserOptions) -> Self {
+        Self {
+            soft_break: options.soft_break,
+            list_indent: options.list_indent,
+            xhtml: options.xhtml,
+            table_alignment_class: options.table_alignment_class.clone(),
+            lazy_images: options.lazy_images,
+            image_src_prefix: options.image_src_prefix.clone(),
+            image_size_root: options.image_size_root.clone(),
+            in_table_body: false,
+        }
+    }
+
+    /// How a void element such as `<br>` is closed: `" />"` in XHTML mode,
+    /// `">"` otherwise.
+    pub fn void_tag_end(&self) -> &'static str {
+        if self.xhtml {
+            " />"
+        } else {
+            ">"
+        }
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn html_renderer(&mut self) -> &mut HtmlRenderer {
+        self
+    }
+}
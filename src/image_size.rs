@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path};
+
+/// Reads the width and height of a PNG, GIF or JPEG image from its header,
+/// without decoding the image. Returns `None` if the file cannot be read or is
+/// in another format.
+///
+/// `relative` is looked up under `root` and must stay inside it: paths with
+/// `..` components, or resolving through a symlink to somewhere else, and
+/// anything but a regular file are never opened.
+pub(crate) fn image_size(root: &Path, relative: &str) -> Option<(u32, u32)> {
+    let relative = Path::new(relative.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+    let root = root.canonicalize().ok()?;
+    let path = root.join(relative).canonicalize().ok()?;
+    if !path.starts_with(&root) || !path.metadata().ok()?.is_file() {
+        return None;
+    }
+    let file = File::open(path).ok()?;
+    read_image_size(BufReader::new(file)).ok().flatten()
+}
+
+fn read_image_size<R: Read>(mut reader: R) -> io::Result<Option<(u32, u32)>> {
+    let mut header = [0; 10];
+    reader.read_exact(&mut header)?;
+
+    match header {
+        // PNG: the IHDR chunk always comes first, holding big-endian sizes
+        [0x89, b'P', b'N', b'G', ..] => {
+            let mut ihdr = [0; 14];
+            reader.read_exact(&mut ihdr)?;
+            if &ihdr[2..6] != b"IHDR" {
+                return Ok(None);
+            }
+            let width = u32::from_be_bytes(ihdr[6..10].try_into().unwrap());
+            let height = u32::from_be_bytes(ihdr[10..14].try_into().unwrap());
+            Ok(Some((width, height)))
+        }
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', w0, w1, h0, h1] => Ok(Some((
+            u16::from_le_bytes([w0, w1]).into(),
+            u16::from_le_bytes([h0, h1]).into(),
+        ))),
+        [0xFF, 0xD8, ..] => {
+            let mut reader = io::Cursor::new(header[2..].to_vec()).chain(reader);
+            jpeg_size(&mut reader)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Walks the JPEG segments up to the start-of-frame marker holding the size.
+fn jpeg_size<R: Read>(reader: &mut R) -> io::Result<Option<(u32, u32)>> {
+    loop {
+        let mut marker = [0; 2];
+        reader.read_exact(&mut marker)?;
+        if marker[0] != 0xFF {
+            return Ok(None);
+        }
+
+        let mut length = [0; 2];
+        reader.read_exact(&mut length)?;
+        let length = u16::from_be_bytes(length);
+        if length < 2 {
+            return Ok(None);
+        }
+
+        // SOF0 to SOF15, except the DHT, JPG and DAC markers sharing the range
+        if matches!(marker[1], 0xC0..=0xCF) && !matches!(marker[1], 0xC4 | 0xC8 | 0xCC) {
+            let mut frame = [0; 5];
+            reader.read_exact(&mut frame)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]);
+            let width = u16::from_be_bytes([frame[3], frame[4]]);
+            return Ok(Some((width.into(), height.into())));
+        }
+
+        let skip = u64::from(length - 2);
+        if io::copy(&mut reader.by_ref().take(skip), &mut io::sink())? < skip {
+            return Ok(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(bytes: &[u8]) -> Option<(u32, u32)> {
+        read_image_size(bytes).ok().flatten()
+    }
+
+    #[test]
+    fn test_png_size() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        png.extend_from_slice(&[8, 6, 0, 0, 0]);
+        assert_eq!(size(&png), Some((640, 480)));
+    }
+
+    #[test]
+    fn test_gif_size() {
+        assert_eq!(size(b"GIF89a\x20\x03\x58\x02\0\0"), Some((800, 600)));
+        assert_eq!(size(b"GIF87a\x01\0\x01\0"), Some((1, 1)));
+    }
+
+    #[test]
+    fn test_jpeg_size() {
+        let jpeg = [
+            0xFF, 0xD8, // SOI
+            0xFF, 0xE0, 0x00, 0x06, b'J', b'F', b'I', b'F', // APP0, skipped
+            0xFF, 0xC4, 0x00, 0x03, 0x00, // DHT, not a frame
+            0xFF, 0xC2, 0x00, 0x0B, 0x08, 0x01, 0x2C, 0x01, 0x90, // SOF2, 400x300
+        ];
+        assert_eq!(size(&jpeg), Some((400, 300)));
+    }
+
+    #[test]
+    fn test_unknown_or_truncated() {
+        assert_eq!(size(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+        assert_eq!(size(b"\x89PNG\r\n\x1a\n\0\0"), None);
+        assert_eq!(size(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x00]), None);
+        assert_eq!(image_size(Path::new("."), "does/not/exist.png"), None);
+    }
+
+    #[test]
+    fn test_paths_stay_inside_root() {
+        let dir = std::env::temp_dir().join(format!("md2html-root-{}", std::process::id()));
+        let root = dir.join("root");
+        std::fs::create_dir_all(root.join("img")).unwrap();
+        std::fs::write(root.join("img/dot.gif"), b"GIF89a\x01\0\x01\0").unwrap();
+        std::fs::write(dir.join("outside.gif"), b"GIF89a\x01\0\x01\0").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("outside.gif"), root.join("link.gif")).unwrap();
+
+        let results = [
+            image_size(&root, "/img/dot.gif"),
+            image_size(&root, "./img/dot.gif"),
+            image_size(&root, "../outside.gif"),
+            image_size(&root, "img/../../outside.gif"),
+            image_size(&root, "img"),
+            image_size(&root, "link.gif"),
+        ];
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            results,
+            [Some((1, 1)), Some((1, 1)), None, None, None, None]
+        );
+    }
+}
@@ -1,365 +1,1878 @@
-use std::fmt::Write;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::{fmt, io};
 
+use autolink::{scan_autolink, scan_extended_email, scan_extended_url, Autolink};
+use emphasis::{resolve_emphasis, scan_delimiter_run};
+use entities::scan_entity;
+
+mod autolink;
+mod emphasis;
+mod entities;
+mod event;
+#[cfg(feature = "highlight")]
+mod highlight;
+mod html;
+mod image_size;
+mod options;
+mod raw_html;
+mod render;
 mod simd;
-use simd::{detect_line_type_simd, find_delimiter_simd, html_escape_simd_into, LineType};
+mod stream;
+
+pub use event::{Alignment, Event, LinkDefinition, Tag};
+#[cfg(feature = "highlight")]
+pub use highlight::highlight_into;
+pub use html::{push_html, HtmlRenderer};
+pub use options::{ParserOptions, SoftBreakStyle};
+use raw_html::{html_block_start, scan_inline_html, UnterminatedHtml};
+use render::render_blocks;
+pub use render::{escape_href_into, escape_html_into, render, Renderer};
+use simd::{detect_line_type_simd, find_delimiter_simd, indent_columns, LineType};
+pub use stream::ReaderParser;
 
 pub struct MarkdownParser<'a> {
     input: &'a str,
+    options: ParserOptions,
+    /// Definitions supplied by the caller, for labels the document does not
+    /// define itself
+    seeded: Cow<'a, HashMap<String, LinkDefinition<'a>>>,
+    /// Definitions found in the document, by normalized label
+    definitions: RefCell<HashMap<String, LinkDefinition<'a>>>,
+    /// Set once the whole document has been scanned for definitions, so that
+    /// references can resolve to definitions further down
+    definitions_collected: Cell<bool>,
+    /// Set during that scan, which skips inline content
+    collecting: Cell<bool>,
+    /// Set while parsing the text of a link
+    in_link: Cell<bool>,
+    /// Footnote definitions found in the document, by normalized label, as
+    /// the lines of their content
+    footnote_definitions: RefCell<HashMap<String, Vec<&'a str>>>,
+    /// Labels of the footnotes referenced so far, in order of their first
+    /// reference, with how many references each has had
+    footnote_references: RefCell<Vec<(String, usize)>>,
 }
 
 impl<'a> MarkdownParser<'a> {
     pub fn new(input: &'a str) -> Self {
-        Self { input }
+        Self::with_options(input, ParserOptions::default())
+    }
+
+    pub fn with_options(input: &'a str, options: ParserOptions) -> Self {
+        Self {
+            input,
+            options,
+            seeded: Cow::Owned(HashMap::new()),
+            definitions: RefCell::new(HashMap::new()),
+            definitions_collected: Cell::new(false),
+            collecting: Cell::new(false),
+            in_link: Cell::new(false),
+            footnote_definitions: RefCell::new(HashMap::new()),
+            footnote_references: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// A parser for part of a streamed document, which resolves references
+    /// only to `seeded` and to definitions earlier in `input`. Footnotes are
+    /// collected at the end of a document, so they are not recognised.
+    pub(crate) fn streaming(
+        input: &'a str,
+        options: ParserOptions,
+        seeded: &'a HashMap<String, LinkDefinition<'a>>,
+    ) -> Self {
+        let parser = Self {
+            seeded: Cow::Borrowed(seeded),
+            ..Self::with_options(input, options.footnotes(false))
+        };
+        parser.definitions_collected.set(true);
+        parser
+    }
+
+    /// Adds a definition for reference links such as `[text][label]`, e.g. to
+    /// share links across documents. A definition of the same label in the
+    /// document takes precedence.
+    ///
+    /// ```
+    /// use md2html::{LinkDefinition, MarkdownParser};
+    ///
+    /// let parser = MarkdownParser::new("See the [docs].").with_link_definition(
+    ///     "Docs",
+    ///     LinkDefinition {
+    ///         dest_url: "https://example.com/docs".into(),
+    ///         title: "".into(),
+    ///     },
+    /// );
+    /// assert_eq!(
+    ///     parser.parse(),
+    ///     "<p>See the <a href=\"https://example.com/docs\">docs</a>.</p>\n"
+    /// );
+    /// ```
+    pub fn with_link_definition(mut self, label: &str, definition: LinkDefinition<'a>) -> Self {
+        self.seeded
+            .to_mut()
+            .insert(normalize_label(label), definition);
+        self
+    }
+
+    /// Returns the link reference definitions in effect, those of the document
+    /// along with the ones it did not override from
+    /// [`with_link_definition`](Self::with_link_definition).
+    ///
+    /// Labels are normalized: case-folded, with runs of whitespace collapsed
+    /// into one space. [`link_definition`](Self::link_definition) looks up an
+    /// unnormalized label.
+    pub fn link_definitions(&self) -> HashMap<String, LinkDefinition<'a>> {
+        self.collect_definitions();
+        let mut definitions = self.seeded.clone().into_owned();
+        definitions.extend(self.definitions.borrow().clone());
+        definitions
+    }
+
+    /// Looks up the definition that `[text][label]` would link to.
+    pub fn link_definition(&self, label: &str) -> Option<LinkDefinition<'a>> {
+        self.collect_definitions();
+        self.link_definition_of(label)
+    }
+
+    /// The definitions recorded while parsing, for a [`ReaderParser`] to carry
+    /// over to the rest of its input.
+    pub(crate) fn into_definitions(self) -> HashMap<String, LinkDefinition<'a>> {
+        self.definitions.into_inner()
+    }
+
+    /// Scans the document for link reference definitions, once, so that
+    /// references resolve regardless of where the definitions are.
+    fn collect_definitions(&self) {
+        if self.definitions_collected.replace(true) || !self.input.contains("]:") {
+            return;
+        }
+        self.collecting.set(true);
+        self.events().for_each(drop);
+        self.collecting.set(false);
+    }
+
+    /// Creates a [`ReaderParser`] that converts Markdown from `reader` block
+    /// by block, without holding the whole document in memory.
+    pub fn from_reader<R: io::BufRead>(reader: R) -> ReaderParser<R> {
+        ReaderParser::new(reader)
     }
 
     pub fn parse(&self) -> String {
-        let lines: Vec<&str> = self.input.lines().collect();
+        self.parse_with_renderer(&mut HtmlRenderer::with_options(&self.options))
+    }
+
+    /// Writes the HTML to a [`fmt::Write`] sink one top-level block at a time,
+    /// so only the current block's output is buffered.
+    pub fn parse_into<W: fmt::Write + ?Sized>(&self, writer: &mut W) -> fmt::Result {
+        let mut renderer = HtmlRenderer::with_options(&self.options);
+        let mut buffer = String::new();
+        render_blocks(&mut renderer, &mut buffer, self.events(), |block| {
+            writer.write_str(block)?;
+            block.clear();
+            Ok(())
+        })
+    }
+
+    /// Writes the HTML to an [`io::Write`] sink one top-level block at a time,
+    /// so memory use is bounded by the largest block rather than the document.
+    pub fn render_to_writer<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut renderer = HtmlRenderer::with_options(&self.options);
+        let mut buffer = String::new();
+        render_blocks(&mut renderer, &mut buffer, self.events(), |block| {
+            writer.write_all(block.as_bytes())?;
+            block.clear();
+            Ok::<(), io::Error>(())
+        })?;
+        writer.flush()
+    }
+
+    /// Renders the document through a custom [`Renderer`].
+    pub fn parse_with_renderer<R: Renderer + ?Sized>(&self, renderer: &mut R) -> String {
         let estimated_capacity = self.input.len() * 2;
         let mut output = String::with_capacity(estimated_capacity);
+        render(renderer, &mut output, self.events());
+        output
+    }
+
+    /// Returns the document as a stream of [`Event`]s.
+    ///
+    /// The iterator parses one top-level block at a time as it advances, so
+    /// only the events of the current block are held in memory.
+    pub fn events(&self) -> Events<'_, 'a> {
+        self.collect_definitions();
+        self.footnote_references.borrow_mut().clear();
+        Events {
+            parser: self,
+            lines: self.input.lines().collect(),
+            position: 0,
+            pending: Vec::new().into_iter(),
+            footnotes_done: false,
+        }
+    }
+
+    /// Parses a run of lines as a sequence of blocks. Container blocks such as
+    /// block quotes and list items strip their markers and call back into this
+    /// method, so the inner content gets the full block grammar.
+    ///
+    /// Returns true if a blank line separates two of the blocks, which is what
+    /// makes a list item loose.
+    fn parse_blocks(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> bool {
         let mut i = 0;
+        let mut seen_block = false;
+        let mut pending_blank = false;
+        let mut blank_between = false;
 
         while i < lines.len() {
+            if lines[i].trim().is_empty() {
+                pending_blank = seen_block;
+                i += 1;
+                continue;
+            }
+            blank_between |= pending_blank;
+            pending_blank = false;
+            seen_block = true;
+
+            i += self.parse_block(&lines[i..], events);
+        }
+
+        blank_between
+    }
+
+    /// Parses the block starting at the first line, returning the number of
+    /// lines it consumed.
+    fn parse_block(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> usize {
+        let line = lines[0];
+
+        // Use SIMD-accelerated line type detection
+        match detect_line_type_simd(line) {
+            LineType::Empty => 1,
+            LineType::Header(level) => {
+                let content = line.trim_start().get((level + 1)..).unwrap_or("").trim();
+                events.push(Event::Start(Tag::Heading(level)));
+                self.parse_inlines(content, events);
+                events.push(Event::End(Tag::Heading(level)));
+                1
+            }
+            LineType::CodeBlock => self.parse_code_block(lines, events),
+            LineType::IndentedCode => self.parse_indented_code(lines, events),
+            LineType::ThematicBreak => {
+                events.push(Event::Rule);
+                1
+            }
+            LineType::UnorderedList | LineType::OrderedList => self.parse_list(lines, events),
+            LineType::BlockQuote => self.parse_blockquote(lines, events),
+            LineType::Paragraph
+                if self.options.footnotes && footnote_definition(line).is_some() =>
+            {
+                self.parse_footnote_definition(lines)
+            }
+            LineType::Paragraph if self.options.raw_html && html_block_start(line).is_some() => {
+                self.parse_html_block(lines, events)
+            }
+            // A table starts like a paragraph and is only recognised by the
+            // delimiter row that follows its header.
+            LineType::Paragraph if self.options.tables && self.is_table_start(lines) => {
+                self.parse_table(lines, events)
+            }
+            LineType::Paragraph => self.parse_paragraph(lines, events),
+        }
+    }
+
+    fn parse_code_block(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> usize {
+        let Some(fence) = code_fence(lines[0]) else {
+            return self.parse_paragraph(lines, events);
+        };
+        let mut code = String::with_capacity(512);
+
+        // An unclosed fence runs to the end of its container
+        let mut i = 1;
+        while i < lines.len() && !fence.is_closed_by(lines[i]) {
+            // Content lines lose as much indentation as the opening fence had
             let line = lines[i];
+            let strip = line
+                .bytes()
+                .take(fence.indent)
+                .take_while(|&b| b == b' ')
+                .count();
+            code.push_str(&line[strip..]);
+            code.push('\n');
+            i += 1;
+        }
 
-            // Use SIMD-accelerated line type detection
-            match detect_line_type_simd(line) {
-                LineType::Empty => {
-                    i += 1;
-                    continue;
-                }
-                LineType::Header(level) => {
-                    let content = line.trim_start().get((level + 1)..).unwrap_or("").trim();
-                    let mut result = String::with_capacity(content.len() + 20);
-                    write!(result, "<h{level}>").unwrap();
-                    self.process_inline_elements_into(content, &mut result);
-                    write!(result, "</h{level}>").unwrap();
-                    output.push_str(&result);
-                    output.push('\n');
-                }
-                LineType::CodeBlock => {
-                    let (code_block, lines_consumed) = self.parse_code_block(&lines[i..]);
-                    output.push_str(&code_block);
-                    output.push('\n');
-                    i += lines_consumed - 1;
-                }
-                LineType::UnorderedList => {
-                    let (list, lines_consumed) = self.parse_unordered_list(&lines[i..]);
-                    output.push_str(&list);
-                    output.push('\n');
-                    i += lines_consumed - 1;
-                }
-                LineType::OrderedList => {
-                    let (list, lines_consumed) = self.parse_ordered_list(&lines[i..]);
-                    output.push_str(&list);
-                    output.push('\n');
-                    i += lines_consumed - 1;
-                }
-                LineType::Paragraph => {
-                    let (paragraph, lines_consumed) = self.parse_paragraph(&lines[i..]);
-                    output.push_str(&paragraph);
-                    output.push('\n');
-                    i += lines_consumed - 1;
-                }
+        let info = unescape(fence.info);
+        events.push(Event::Start(Tag::CodeBlock(info.clone())));
+        if !code.is_empty() {
+            events.push(Event::Text(Cow::Owned(code)));
+        }
+        events.push(Event::End(Tag::CodeBlock(info)));
+        // Skip the closing fence, if there is one
+        (i + 1).min(lines.len())
+    }
+
+    fn parse_indented_code(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> usize {
+        // Blank lines inside the block are kept, but trailing ones aren't part
+        // of it
+        let mut end = 0;
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if indent_columns(line) < 4 {
+                break;
             }
+            end = i + 1;
+        }
+
+        let mut code = String::new();
+        for line in &lines[..end] {
+            code.push_str(strip_columns(line, 4));
+            code.push('\n');
+        }
 
+        events.push(Event::Start(Tag::CodeBlock(Cow::Borrowed(""))));
+        events.push(Event::Text(Cow::Owned(code)));
+        events.push(Event::End(Tag::CodeBlock(Cow::Borrowed(""))));
+        end
+    }
+
+    fn parse_html_block(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> usize {
+        let Some(kind) = html_block_start(lines[0]) else {
+            return 1;
+        };
+
+        let mut i = 0;
+        while i < lines.len() {
+            if kind.ends_on(lines[i]) {
+                i += 1;
+                break;
+            }
+            // Blocks that don't end on a closing marker run to a blank line
+            if i > 0 && lines[i].trim().is_empty() {
+                break;
+            }
             i += 1;
         }
 
-        output
+        let mut html = lines[..i].join("\n");
+        html.push('\n');
+        events.push(Event::Html(Cow::Owned(html)));
+        i
     }
 
-    fn parse_header(&self, line: &str) -> Option<String> {
-        let trimmed = line.trim_start();
-        let level = trimmed.bytes().take_while(|&b| b == b'#').count();
+    /// Recognises a list item marker and measures where the item's content
+    /// starts, which is the indentation continuation lines must reach.
+    fn list_marker(&self, line: &str) -> Option<ListMarker> {
+        // `- - -` and `* * *` are breaks, not items
+        if detect_line_type_simd(line) == LineType::ThematicBreak {
+            return None;
+        }
+        let bytes = line.as_bytes();
+        let indent = indentation(line);
+        let rest = &bytes[indent..];
+
+        let (marker, marker_len, start) = match rest {
+            [b @ (b'-' | b'*' | b'+'), b' ' | b'\t', ..] => (*b, 1, None),
+            [b'0'..=b'9', ..] => {
+                let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+                let (Some(&delimiter @ (b'.' | b')')), Some(b' ' | b'\t')) =
+                    (rest.get(digits), rest.get(digits + 1))
+                else {
+                    return None;
+                };
+                if digits > 9 {
+                    return None;
+                }
+                let start = line[indent..indent + digits].parse().ok()?;
+                (delimiter, digits + 1, Some(start))
+            }
+            _ => return None,
+        };
+
+        // Tabs after the marker advance to the next multiple of four columns
+        let marker_end = indent + marker_len;
+        let mut columns = marker_end;
+        let mut content_start = marker_end;
+        for &byte in &bytes[marker_end..] {
+            match byte {
+                b' ' => columns += 1,
+                b'\t' => columns += 4 - columns % 4,
+                _ => break,
+            }
+            content_start += 1;
+        }
 
-        if level > 0 && level <= 6 && trimmed.as_bytes().get(level) == Some(&b' ') {
-            let content = trimmed[(level + 1)..].trim();
-            let mut result = String::with_capacity(content.len() + 20);
-            write!(result, "<h{level}>").unwrap();
-            self.process_inline_elements_into(content, &mut result);
-            write!(result, "</h{level}>").unwrap();
-            Some(result)
-        } else {
-            None
+        // Content indented five or more columns past the marker is an indented
+        // code block inside the item, so only one column belongs to the marker.
+        if columns - marker_end > 4 || content_start == bytes.len() {
+            columns = marker_end + 1;
+            content_start = marker_end + 1;
         }
+
+        Some(ListMarker {
+            marker,
+            start,
+            content_offset: columns,
+            content_start,
+        })
     }
 
-    #[inline]
-    fn is_code_block_start(&self, line: &str) -> bool {
-        line.trim_start().starts_with("```")
+    fn parse_list(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> usize {
+        let Some(first) = self.list_marker(lines[0]) else {
+            return 1;
+        };
+
+        let mut items: Vec<Vec<&'a str>> = Vec::new();
+        let mut loose = false;
+        let mut i = 0;
+
+        while i < lines.len() {
+            let Some(marker) = self.list_marker(lines[i]) else {
+                break;
+            };
+            if marker.marker != first.marker {
+                break;
+            }
+
+            let mut item = ContainerLines::default();
+            item.push(&lines[i][marker.content_start..]);
+            let mut blanks = 0;
+            i += 1;
+
+            while i < lines.len() {
+                let line = lines[i];
+                if line.trim().is_empty() {
+                    blanks += 1;
+                } else if indent_columns(line) >= marker.content_offset {
+                    for _ in 0..blanks {
+                        item.push("");
+                    }
+                    item.push(strip_columns(line, marker.content_offset));
+                    blanks = 0;
+                } else if blanks == 0 && continues_paragraph(line) && self.ends_in_paragraph(&item)
+                {
+                    // Lazy continuation of the item's paragraph
+                    item.push(line.trim_start());
+                } else {
+                    break;
+                }
+                i += 1;
+            }
+
+            items.push(item.lines);
+
+            let continues = i < lines.len()
+                && self
+                    .list_marker(lines[i])
+                    .is_some_and(|next| next.marker == first.marker);
+            if continues {
+                loose |= blanks > 0;
+            } else {
+                // Trailing blank lines belong to whatever follows the list
+                i -= blanks;
+                break;
+            }
+        }
+
+        // A list is also loose when any item has a blank line between two of
+        // its direct children, which is only known once the items are parsed.
+        // Items of a tight list hold their paragraphs' content directly, so
+        // the tags of the paragraphs directly inside the items are dropped.
+        let mark = events.len();
+        let blank_inside = self.parse_list_items(&first, &items, events);
+        if !loose && !blank_inside {
+            let mut depth = 0;
+            let mut tail = events.split_off(mark);
+            tail.retain(|event| match event {
+                Event::Start(tag) => {
+                    depth += 1;
+                    !(depth == 3 && *tag == Tag::Paragraph)
+                }
+                Event::End(tag) => {
+                    depth -= 1;
+                    !(depth == 2 && *tag == Tag::Paragraph)
+                }
+                _ => true,
+            });
+            events.append(&mut tail);
+        }
+        i
     }
 
-    fn parse_code_block(&self, lines: &[&str]) -> (String, usize) {
-        if lines.is_empty() || !self.is_code_block_start(lines[0]) {
-            return (String::new(), 0);
+    fn parse_list_items(
+        &self,
+        marker: &ListMarker,
+        items: &[Vec<&'a str>],
+        events: &mut Vec<Event<'a>>,
+    ) -> bool {
+        let mut blank_inside = false;
+        events.push(Event::Start(Tag::List(marker.start)));
+        for item in items {
+            let task = item
+                .first()
+                .filter(|_| self.options.task_lists)
+                .and_then(|line| task_marker(line));
+            let Some((checked, rest)) = task else {
+                events.push(Event::Start(Tag::Item(None)));
+                blank_inside |= self.parse_blocks(item, events);
+                events.push(Event::End(Tag::Item(None)));
+                continue;
+            };
+
+            let mut item = item.clone();
+            item[0] = rest;
+            events.push(Event::Start(Tag::Item(Some(checked))));
+            blank_inside |= self.parse_blocks(&item, events);
+            events.push(Event::End(Tag::Item(Some(checked))));
         }
+        events.push(Event::End(Tag::List(marker.start)));
+        blank_inside
+    }
 
-        let mut result = String::with_capacity(512);
-        result.push_str("<pre><code>");
+    /// Records a footnote definition, `[^label]: text`, along with the lines
+    /// indented by four columns that follow it. Its content is only parsed
+    /// with the other footnotes at the end of the document.
+    fn parse_footnote_definition(&self, lines: &[&'a str]) -> usize {
+        let Some((label, first)) = footnote_definition(lines[0]) else {
+            return 1;
+        };
 
+        let mut content = ContainerLines::default();
+        content.push(first);
+        let mut blanks = 0;
         let mut i = 1;
-        while i < lines.len() && !lines[i].trim_start().starts_with("```") {
-            if i > 1 {
-                result.push('\n');
+        while i < lines.len() {
+            let line = lines[i];
+            if line.trim().is_empty() {
+                blanks += 1;
+            } else if indent_columns(line) >= 4 {
+                for _ in 0..blanks {
+                    content.push("");
+                }
+                content.push(strip_columns(line, 4));
+                blanks = 0;
+            } else if blanks == 0
+                && continues_paragraph(line)
+                && footnote_definition(line).is_none()
+                && self.ends_in_paragraph(&content)
+            {
+                // Lazy continuation of the footnote's paragraph, up to the
+                // next definition
+                content.push(line.trim_start());
+            } else {
+                break;
             }
-            html_escape_simd_into(lines[i], &mut result);
             i += 1;
         }
 
-        result.push_str("</code></pre>");
-        (result, i + 1)
+        // Link reference definitions inside the footnote apply to the whole
+        // document, like those anywhere else
+        if self.collecting.get() {
+            self.parse_blocks(&content.lines, &mut Vec::new());
+        }
+        self.footnote_definitions
+            .borrow_mut()
+            .entry(normalize_label(label))
+            .or_insert(content.lines);
+        i - blanks
     }
 
-    #[inline]
-    fn is_unordered_list_item(&self, line: &str) -> bool {
-        let trimmed = line.trim_start();
-        trimmed.len() >= 2 && matches!(trimmed.as_bytes(), [b'-' | b'*' | b'+', b' ', ..])
+    /// Emits the referenced footnotes in a [`Tag::Footnotes`] section, in the
+    /// order they were first referenced.
+    fn parse_footnotes(&self, events: &mut Vec<Event<'a>>) {
+        // Footnotes referenced only from other footnotes join the end of the
+        // list while it is being parsed
+        let mut contents = Vec::new();
+        loop {
+            let Some((label, _)) = self
+                .footnote_references
+                .borrow()
+                .get(contents.len())
+                .cloned()
+            else {
+                break;
+            };
+            let lines = self.footnote_definitions.borrow()[&label].clone();
+            let mut content = Vec::new();
+            self.parse_blocks(&lines, &mut content);
+            contents.push(content);
+        }
+        if contents.is_empty() {
+            return;
+        }
+
+        events.push(Event::Start(Tag::Footnotes));
+        let references = self.footnote_references.borrow();
+        for (i, content) in contents.into_iter().enumerate() {
+            let tag = Tag::FootnoteDefinition {
+                number: i + 1,
+                references: references[i].1,
+            };
+            events.push(Event::Start(tag.clone()));
+            events.extend(content);
+            events.push(Event::End(tag));
+        }
+        events.push(Event::End(Tag::Footnotes));
+    }
+
+    /// Strips one level of `>` marker (and the optional space after it).
+    fn strip_blockquote_marker<'l>(&self, line: &'l str) -> Option<&'l str> {
+        let rest = line.trim_start().strip_prefix('>')?;
+        Some(rest.strip_prefix(' ').unwrap_or(rest))
+    }
+
+    /// Returns true when the innermost block of `lines` is an open paragraph,
+    /// which is the only context that accepts a lazy continuation line.
+    fn ends_in_paragraph(&self, lines: &ContainerLines<'_>) -> bool {
+        lines.open_fence.is_none()
+            && lines
+                .lines
+                .last()
+                .is_some_and(|last| self.is_paragraph_line(last))
+    }
+
+    /// Returns true if `line` is paragraph text, also inside block quote
+    /// markers.
+    fn is_paragraph_line(&self, line: &str) -> bool {
+        match detect_line_type_simd(line) {
+            LineType::Paragraph => true,
+            LineType::BlockQuote => self
+                .strip_blockquote_marker(line)
+                .is_some_and(|inner| self.is_paragraph_line(inner)),
+            _ => false,
+        }
     }
 
-    fn parse_unordered_list(&self, lines: &[&str]) -> (String, usize) {
-        let mut result = String::with_capacity(256);
-        result.push_str("<ul>\n");
+    fn parse_blockquote(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> usize {
+        let mut inner = ContainerLines {
+            lines: Vec::with_capacity(lines.len()),
+            open_fence: None,
+        };
         let mut i = 0;
 
-        while i < lines.len() && self.is_unordered_list_item(lines[i]) {
-            let content = lines[i].trim_start().get(2..).unwrap_or("").trim();
-            result.push_str("  <li>");
-            self.process_inline_elements_into(content, &mut result);
-            result.push_str("</li>\n");
+        while i < lines.len() {
+            let line = lines[i];
+            if let Some(stripped) = self.strip_blockquote_marker(line) {
+                inner.push(stripped);
+            } else if continues_paragraph(line) && self.ends_in_paragraph(&inner) {
+                // Lazy continuation: a plain text line continues the quoted
+                // paragraph even without its `>` marker.
+                inner.push(line);
+            } else {
+                break;
+            }
             i += 1;
         }
 
-        result.push_str("</ul>");
-        (result, i)
+        events.push(Event::Start(Tag::BlockQuote));
+        self.parse_blocks(&inner.lines, events);
+        events.push(Event::End(Tag::BlockQuote));
+        i
     }
 
-    #[inline]
-    fn is_ordered_list_item(&self, line: &str) -> bool {
-        let trimmed = line.trim_start();
-        if let Some(dot_pos) = trimmed.find(". ") {
-            dot_pos > 0 && trimmed[..dot_pos].bytes().all(|b| b.is_ascii_digit())
-        } else {
-            false
+    /// A table needs a header row containing a pipe, directly followed by a
+    /// delimiter row with the same number of cells.
+    fn is_table_start(&self, lines: &[&str]) -> bool {
+        if lines.len() < 2 || !lines[0].contains('|') {
+            return false;
+        }
+        match self.parse_table_alignments(lines[1]) {
+            Some(alignments) => alignments.len() == split_table_row(lines[0]).len(),
+            None => false,
         }
     }
 
-    fn parse_ordered_list(&self, lines: &[&str]) -> (String, usize) {
-        let mut result = String::with_capacity(256);
-        result.push_str("<ol>\n");
-        let mut i = 0;
+    /// Parses a delimiter row such as `|---|:---:|--:|` into column alignments.
+    fn parse_table_alignments(&self, line: &str) -> Option<Vec<Alignment>> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || !trimmed.contains('-') {
+            return None;
+        }
 
-        while i < lines.len() && self.is_ordered_list_item(lines[i]) {
-            let trimmed = lines[i].trim_start();
-            if let Some(dot_pos) = trimmed.find(". ") {
-                let content = trimmed[(dot_pos + 2)..].trim();
-                result.push_str("  <li>");
-                self.process_inline_elements_into(content, &mut result);
-                result.push_str("</li>\n");
-            }
+        split_table_row(trimmed)
+            .iter()
+            .map(|cell| {
+                let left = cell.starts_with(':');
+                let right = cell.ends_with(':') && cell.len() > 1;
+                let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+                if dashes.is_empty() || !dashes.bytes().all(|b| b == b'-') {
+                    return None;
+                }
+                Some(match (left, right) {
+                    (true, true) => Alignment::Center,
+                    (true, false) => Alignment::Left,
+                    (false, true) => Alignment::Right,
+                    (false, false) => Alignment::None,
+                })
+            })
+            .collect()
+    }
+
+    fn parse_table(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> usize {
+        let alignments = self.parse_table_alignments(lines[1]).unwrap_or_default();
+        let columns = alignments.len();
+        events.push(Event::Start(Tag::Table(alignments.clone())));
+
+        events.push(Event::Start(Tag::TableHead));
+        self.parse_table_cells(split_table_row(lines[0]), columns, events);
+        events.push(Event::End(Tag::TableHead));
+
+        let mut i = 2;
+        while i < lines.len() && detect_line_type_simd(lines[i]) == LineType::Paragraph {
+            events.push(Event::Start(Tag::TableRow));
+            self.parse_table_cells(split_table_row(lines[i]), columns, events);
+            events.push(Event::End(Tag::TableRow));
             i += 1;
         }
 
-        result.push_str("</ol>");
-        (result, i)
+        events.push(Event::End(Tag::Table(alignments)));
+        i
     }
 
-    fn parse_paragraph(&self, lines: &[&str]) -> (String, usize) {
-        let mut paragraph_content = String::with_capacity(256);
-        let mut i = 0;
-        let mut first_line = true;
+    /// Emits one row's cells, padding missing cells and dropping extra ones so
+    /// every row has as many cells as the header.
+    fn parse_table_cells(
+        &self,
+        cells: Vec<Cow<'a, str>>,
+        columns: usize,
+        events: &mut Vec<Event<'a>>,
+    ) {
+        let mut cells = cells.into_iter();
+        for _ in 0..columns {
+            events.push(Event::Start(Tag::TableCell));
+            if let Some(cell) = cells.next() {
+                self.parse_inline_cow(cell, events);
+            }
+            events.push(Event::End(Tag::TableCell));
+        }
+    }
 
+    fn parse_paragraph(&self, lines: &[&'a str], events: &mut Vec<Event<'a>>) -> usize {
+        let mut i = 1;
+        let mut setext_level = None;
         while i < lines.len() {
-            let line = lines[i];
-            if line.trim().is_empty()
-                || self.parse_header(line).is_some()
-                || self.is_code_block_start(line)
-                || self.is_unordered_list_item(line)
-                || self.is_ordered_list_item(line)
+            // An underline turns the paragraph so far into a heading, and
+            // takes precedence over reading `---` as a thematic break
+            setext_level = setext_underline(lines[i]).filter(|_| !self.is_lazy(lines[0], lines[i]));
+            if setext_level.is_some()
+                || !(continues_paragraph(lines[i]) || self.is_list_item_text(lines[i]))
+                || self.interrupts_paragraph_with_html(lines[i])
             {
                 break;
             }
+            i += 1;
+        }
 
-            if !first_line {
-                paragraph_content.push(' ');
+        let mut paragraph_lines = lines[..i].to_vec();
+        paragraph_lines[0] = paragraph_lines[0].trim_start();
+        paragraph_lines[i - 1] = paragraph_lines[i - 1].trim_end();
+        let content = match self.contiguous_span(&paragraph_lines) {
+            Some(span) => Cow::Borrowed(span),
+            None => Cow::Owned(paragraph_lines.join("\n")),
+        };
+
+        let Some(content) = self.strip_link_definitions(content) else {
+            // Nothing but definitions, so an underline has no text to turn
+            // into a heading and is a paragraph of its own
+            if setext_level.is_some() {
+                events.push(Event::Start(Tag::Paragraph));
+                self.parse_inlines(lines[i].trim(), events);
+                events.push(Event::End(Tag::Paragraph));
+                return i + 1;
             }
-            paragraph_content.push_str(line);
-            first_line = false;
-            i += 1;
+            return i;
+        };
+
+        if let Some(level) = setext_level {
+            events.push(Event::Start(Tag::Heading(level)));
+            self.parse_inline_cow(content, events);
+            events.push(Event::End(Tag::Heading(level)));
+            return i + 1;
+        }
+
+        events.push(Event::Start(Tag::Paragraph));
+        self.parse_inline_cow(content, events);
+        events.push(Event::End(Tag::Paragraph));
+        i
+    }
+
+    /// Records the link reference definitions at the start of a paragraph,
+    /// returning the rest of its content, or `None` if nothing is left.
+    fn strip_link_definitions(&self, content: Cow<'a, str>) -> Option<Cow<'a, str>> {
+        if !content.starts_with('[') {
+            return Some(content);
+        }
+
+        let rest = match content {
+            Cow::Borrowed(text) => Cow::Borrowed(self.define_links(text, |definition| definition)),
+            Cow::Owned(text) => {
+                let rest = self.define_links(&text, |definition| definition.into_owned());
+                if rest.len() == text.len() {
+                    Cow::Owned(text)
+                } else {
+                    Cow::Owned(rest.to_string())
+                }
+            }
+        };
+        (!rest.is_empty()).then_some(rest)
+    }
+
+    /// Records the definitions at the start of `text`, keeping the first one
+    /// of each label, and returns the text following them.
+    fn define_links<'t>(
+        &self,
+        text: &'t str,
+        to_definition: impl Fn(LinkDefinition<'t>) -> LinkDefinition<'a>,
+    ) -> &'t str {
+        let mut rest = text;
+        while let Some((label, definition, len)) = link_reference_definition(rest) {
+            self.definitions
+                .borrow_mut()
+                .entry(normalize_label(label))
+                .or_insert_with(|| to_definition(definition));
+            rest = rest[len..].trim_start_matches([' ', '\t']);
+        }
+        rest
+    }
+
+    /// Returns true if `line` is a lazy continuation of a paragraph starting
+    /// at `first`, i.e. was taken from outside the container the paragraph is
+    /// in. Container markers are stripped from a line by slicing, so such a
+    /// line starts at an earlier column of its input line than the paragraph.
+    fn is_lazy(&self, first: &str, line: &str) -> bool {
+        let column = |line: &str| {
+            let offset = (line.as_ptr() as usize).checked_sub(self.input.as_ptr() as usize)?;
+            let before = self.input.get(..offset)?;
+            Some(offset - before.rfind('\n').map_or(0, |newline| newline + 1))
+        };
+        matches!((column(first), column(line)), (Some(first), Some(line)) if line < first)
+    }
+
+    /// Returns true if `line` looks like a list item that can't interrupt a
+    /// paragraph, so it continues the paragraph as text: an empty item, or
+    /// an ordered one that doesn't start at 1.
+    fn is_list_item_text(&self, line: &str) -> bool {
+        matches!(
+            detect_line_type_simd(line),
+            LineType::UnorderedList | LineType::OrderedList
+        ) && self.list_marker(line).is_none_or(|marker| {
+            line[marker.content_start..].trim().is_empty() || marker.start.is_some_and(|n| n != 1)
+        })
+    }
+
+    #[inline]
+    fn interrupts_paragraph_with_html(&self, line: &str) -> bool {
+        self.options.raw_html
+            && html_block_start(line).is_some_and(|kind| kind.can_interrupt_paragraph())
+    }
+
+    /// Returns the lines as one slice of the input when only line endings and
+    /// indentation separate them, so inline events can borrow instead of copy.
+    fn contiguous_span(&self, lines: &[&'a str]) -> Option<&'a str> {
+        let base = self.input.as_ptr() as usize;
+        let offset = |line: &str| (line.as_ptr() as usize).checked_sub(base);
+
+        let start = offset(lines.first()?)?;
+        let mut end = start + lines[0].len();
+        for line in &lines[1..] {
+            let line_start = offset(line)?;
+            let gap = self.input.get(end..line_start)?;
+            let gap = gap.strip_prefix('\r').unwrap_or(gap);
+            if !gap
+                .strip_prefix('\n')?
+                .bytes()
+                .all(|b| b == b' ' || b == b'\t')
+            {
+                return None;
+            }
+            end = line_start + line.len();
         }
 
-        let mut result = String::with_capacity(paragraph_content.len() + 20);
-        result.push_str("<p>");
-        self.process_inline_elements_into(&paragraph_content, &mut result);
-        result.push_str("</p>");
-        (result, i)
+        self.input.get(start..end)
+    }
+
+    /// Parses inline content that is either borrowed from the input or had to
+    /// be assembled into a new string.
+    fn parse_inline_cow(&self, text: Cow<'a, str>, events: &mut Vec<Event<'a>>) {
+        match text {
+            Cow::Borrowed(text) => self.parse_inlines(text, events),
+            Cow::Owned(text) => {
+                let mut inline_events = Vec::new();
+                self.parse_inlines(&text, &mut inline_events);
+                events.extend(
+                    inline_events
+                        .into_iter()
+                        .map(|event| -> Event<'a> { event.into_owned() }),
+                );
+            }
+        }
     }
 
-    fn process_inline_elements_into(&self, text: &str, output: &mut String) {
+    fn parse_inlines<'t>(&self, text: &'t str, events: &mut Vec<Event<'t>>)
+    where
+        'a: 't,
+    {
+        if self.collecting.get() {
+            return;
+        }
+
         let bytes = text.as_bytes();
+        let mut text_start = 0;
         let mut i = 0;
+        let mut delimiters = Vec::new();
+        let mut link_text_ends = None;
+        let mut unterminated_html = UnterminatedHtml::default();
 
         while i < bytes.len() {
-            match bytes[i] {
-                b'*' => {
-                    if i + 1 < bytes.len() && bytes[i + 1] == b'*' {
-                        if let Some(consumed) = self.try_parse_bold(text, i, output) {
-                            i += consumed;
-                            continue;
-                        }
-                    }
-                    if let Some(consumed) = self.try_parse_italic(text, i, output) {
-                        i += consumed;
-                        continue;
-                    }
-                    output.push('*');
-                }
-                b'_' => {
-                    if let Some(consumed) = self.try_parse_italic(text, i, output) {
-                        i += consumed;
-                        continue;
-                    }
-                    output.push('_');
-                }
-                b'`' => {
-                    if let Some(consumed) = self.try_parse_inline_code(text, i, output) {
-                        i += consumed;
-                        continue;
-                    }
-                    output.push('`');
+            // Runs of `*` and `_`, and of the enabled extension markers, are
+            // kept as text until the whole text has been scanned, when
+            // `resolve_emphasis` pairs them up
+            let marker = bytes[i];
+            if matches!(marker, b'*' | b'_' | b'~' | b'=' | b'^') {
+                let run_len = bytes[i..].iter().take_while(|&&b| b == marker).count();
+                if !self.is_delimiter_run(marker, run_len) {
+                    i += run_len;
+                    continue;
                 }
-                b'[' => {
-                    if let Some(consumed) = self.try_parse_link(text, i, output) {
-                        i += consumed;
-                        continue;
-                    }
-                    output.push('[');
+                if text_start < i {
+                    events.push(Event::Text(Cow::Borrowed(&text[text_start..i])));
                 }
-                b'&' => {
-                    output.push_str("&amp;");
+                let delimiter = scan_delimiter_run(text, i, events.len());
+                let run = &text[i..(i + delimiter.len())];
+                events.push(Event::Text(Cow::Borrowed(run)));
+                delimiters.push(delimiter);
+                i += run.len();
+                text_start = i;
+                continue;
+            }
+
+            // Constructs push their events on success; the plain text before
+            // them is then inserted at `mark`.
+            let mark = events.len();
+            let consumed = match bytes[i] {
+                b'`' => self.try_parse_inline_code(text, i, events),
+                b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => {
+                    events.push(Event::Text(Cow::Borrowed(&text[(i + 1)..(i + 2)])));
+                    Some(2)
                 }
-                b'<' => {
-                    output.push_str("&lt;");
+                b'&' => scan_entity(text, i).map(|(value, len)| {
+                    events.push(Event::Text(value));
+                    len
+                }),
+                b'[' => self
+                    .try_parse_footnote_reference(text, i, events)
+                    .or_else(|| {
+                        let ends = link_text_ends.get_or_insert_with(|| LinkTextEnds::new(text));
+                        self.try_parse_link(text, i, ends, events)
+                    }),
+                b'!' if bytes.get(i + 1) == Some(&b'[') => {
+                    let ends = link_text_ends.get_or_insert_with(|| LinkTextEnds::new(text));
+                    self.try_parse_image(text, i, ends, events)
                 }
-                b'>' => {
-                    output.push_str("&gt;");
+                b'<' => match scan_autolink(bytes, i) {
+                    Some(autolink) => Some(push_autolink(text, i + 1, autolink, events) + 2),
+                    None if self.options.raw_html => {
+                        scan_inline_html(bytes, i, &mut unterminated_html).inspect(|&len| {
+                            events.push(Event::InlineHtml(Cow::Borrowed(&text[i..i + len])));
+                        })
+                    }
+                    None => None,
+                },
+                b'w' | b'W' | b'h' | b'H' if self.extended_autolinks() => {
+                    scan_extended_url(bytes, i)
+                        .map(|autolink| push_autolink(text, i, autolink, events))
                 }
-                b'"' => {
-                    output.push_str("&quot;");
+                b'@' if self.extended_autolinks() => {
+                    scan_extended_email(bytes, text_start, i).map(|(start, autolink)| {
+                        // The address starts with the text before the `@`
+                        i = start;
+                        push_autolink(text, start, autolink, events)
+                    })
                 }
-                b'\'' => {
-                    output.push_str("&#39;");
+                b'\n' => {
+                    // Trailing whitespace ends with the line and leading
+                    // whitespace of the next line is not content either.
+                    // A backslash or two spaces at the end make a hard break.
+                    let line = &text[text_start..i];
+                    let line = line.strip_suffix('\r').unwrap_or(line);
+                    let (content, hard) = match line.strip_suffix('\\') {
+                        Some(content) => (content, true),
+                        None => (line.trim_end_matches([' ', '\t']), line.ends_with("  ")),
+                    };
+                    if !content.is_empty() {
+                        events.push(Event::Text(Cow::Borrowed(content)));
+                    }
+                    events.push(if hard {
+                        Event::HardBreak
+                    } else {
+                        Event::SoftBreak
+                    });
+                    i += 1;
+                    while i < bytes.len() && matches!(bytes[i], b' ' | b'\t') {
+                        i += 1;
+                    }
+                    text_start = i;
+                    continue;
                 }
-                _ => {
-                    if let Some(ch) = text[i..].chars().next() {
-                        output.push(ch);
-                        i += ch.len_utf8() - 1;
+                _ => None,
+            };
+
+            match consumed {
+                Some(consumed) => {
+                    if text_start < i {
+                        events.insert(mark, Event::Text(Cow::Borrowed(&text[text_start..i])));
                     }
+                    i += consumed;
+                    text_start = i;
                 }
+                None => i += 1,
             }
-            i += 1;
         }
-    }
 
-    fn try_parse_bold(&self, text: &str, start: usize, output: &mut String) -> Option<usize> {
-        let bytes = text.as_bytes();
-        if start + 3 >= bytes.len() || bytes[start] != b'*' || bytes[start + 1] != b'*' {
-            return None;
+        if text_start < bytes.len() {
+            events.push(Event::Text(Cow::Borrowed(&text[text_start..])));
         }
-
-        // Use SIMD to find closing **
-        if let Some(end_pos) = find_delimiter_simd(text, b'*', start + 2) {
-            if end_pos + 1 < bytes.len() && bytes[end_pos + 1] == b'*' {
-                output.push_str("<strong>");
-                let content = &text[(start + 2)..end_pos];
-                self.process_inline_elements_into(content, output);
-                output.push_str("</strong>");
-                return Some(end_pos - start + 2);
-            }
+        if !delimiters.is_empty() {
+            resolve_emphasis(text, events, delimiters);
         }
-        None
     }
 
-    fn try_parse_italic(&self, text: &str, start: usize, output: &mut String) -> Option<usize> {
-        let bytes = text.as_bytes();
-        if start >= bytes.len() {
+    /// Parses a reference to a defined footnote, `[^label]`, numbering the
+    /// footnote if this is its first reference.
+    fn try_parse_footnote_reference<'t>(
+        &self,
+        text: &'t str,
+        start: usize,
+        events: &mut Vec<Event<'t>>,
+    ) -> Option<usize>
+    where
+        'a: 't,
+    {
+        if !self.options.footnotes {
             return None;
         }
-
-        let delimiter = bytes[start];
-        if delimiter != b'*' && delimiter != b'_' {
+        let label = footnote_label(&text[start..])?;
+        let key = normalize_label(label);
+        if !self.footnote_definitions.borrow().contains_key(&key) {
             return None;
         }
 
-        // Use SIMD to find closing delimiter
-        if let Some(end_pos) = find_delimiter_simd(text, delimiter, start + 1) {
-            output.push_str("<em>");
-            let content = &text[(start + 1)..end_pos];
-            output.push_str(content);
-            output.push_str("</em>");
-            return Some(end_pos - start + 1);
-        }
-        None
+        let mut references = self.footnote_references.borrow_mut();
+        let index = match references
+            .iter()
+            .position(|(referenced, _)| *referenced == key)
+        {
+            Some(index) => index,
+            None => {
+                references.push((key, 0));
+                references.len() - 1
+            }
+        };
+        references[index].1 += 1;
+        events.push(Event::FootnoteReference {
+            label: Cow::Borrowed(label),
+            number: index + 1,
+            occurrence: references[index].1,
+        });
+        Some(label.len() + 3)
     }
 
-    fn try_parse_inline_code(
+    fn try_parse_inline_code<'t>(
         &self,
-        text: &str,
+        text: &'t str,
         start: usize,
-        output: &mut String,
-    ) -> Option<usize> {
-        let bytes = text.as_bytes();
-        if start >= bytes.len() || bytes[start] != b'`' {
-            return None;
-        }
+        events: &mut Vec<Event<'t>>,
+    ) -> Option<usize>
+    where
+        'a: 't,
+    {
+        let Some(span) = code_span(text, start) else {
+            // Without a closing run of the same length the backticks are
+            // literal, and no shorter run inside them opens a span either
+            let len = backtick_run(text.as_bytes(), start);
+            events.push(Event::Text(Cow::Borrowed(&text[start..(start + len)])));
+            return Some(len);
+        };
 
-        // Use SIMD to find closing backtick
-        if let Some(end_pos) = find_delimiter_simd(text, b'`', start + 1) {
-            output.push_str("<code>");
-            let content = &text[(start + 1)..end_pos];
-            html_escape_simd_into(content, output);
-            output.push_str("</code>");
-            return Some(end_pos - start + 1);
-        }
-        None
+        let content = &text[span.content.clone()];
+        events.push(Event::Code(code_span_content(content)));
+        Some(span.end - start)
     }
 
-    fn try_parse_link(&self, text: &str, start: usize, output: &mut String) -> Option<usize> {
-        let bytes = text.as_bytes();
-        if start >= bytes.len() || bytes[start] != b'[' {
-            return None;
+    fn try_parse_link<'t>(
+        &self,
+        text: &'t str,
+        start: usize,
+        link_text_ends: &LinkTextEnds,
+        events: &mut Vec<Event<'t>>,
+    ) -> Option<usize>
+    where
+        'a: 't,
+    {
+        let link = self.link(text, start, link_text_ends)?;
+        let tag = Tag::Link {
+            dest_url: link.dest_url,
+            title: link.title,
+        };
+
+        // Links cannot contain other links, so bare URLs stay text
+        events.push(Event::Start(tag.clone()));
+        let in_link = self.in_link.replace(true);
+        self.parse_inlines(link.text, events);
+        self.in_link.set(in_link);
+        events.push(Event::End(tag));
+
+        Some(link.len)
+    }
+
+    #[inline]
+    fn extended_autolinks(&self) -> bool {
+        self.options.extended_autolinks && !self.in_link.get()
+    }
+
+    /// Whether a run of `len` `marker` characters can make emphasis or one of
+    /// the enabled inline extensions. Other runs are plain text.
+    fn is_delimiter_run(&self, marker: u8, len: usize) -> bool {
+        match (marker, len) {
+            (b'*' | b'_', _) => true,
+            (b'~', 2) => self.options.strikethrough,
+            (b'~', 1) => self.options.subscript,
+            (b'=', 2) => self.options.mark,
+            (b'^', 1) => self.options.superscript,
+            _ => false,
         }
+    }
 
-        // Use SIMD to find closing bracket and parenthesis
-        let bracket_end = find_delimiter_simd(text, b']', start + 1)?;
+    fn try_parse_image<'t>(
+        &self,
+        text: &'t str,
+        start: usize,
+        link_text_ends: &LinkTextEnds,
+        events: &mut Vec<Event<'t>>,
+    ) -> Option<usize>
+    where
+        'a: 't,
+    {
+        let link = self.link(text, start + 1, link_text_ends)?;
+        let tag = Tag::Image {
+            dest_url: link.dest_url,
+            title: link.title,
+        };
 
-        if bracket_end + 1 >= bytes.len() || bytes[bracket_end + 1] != b'(' {
-            return None;
+        // The alt text may contain formatting, which renderers flatten
+        events.push(Event::Start(tag.clone()));
+        self.parse_inlines(link.text, events);
+        events.push(Event::End(tag));
+
+        Some(link.len + 1)
+    }
+
+    /// Parses the link starting at the `[` at `start`: an inline link, or a
+    /// full (`[text][label]`), collapsed (`[label][]`) or shortcut (`[label]`)
+    /// reference to a definition.
+    fn link<'t>(
+        &self,
+        text: &'t str,
+        start: usize,
+        link_text_ends: &LinkTextEnds,
+    ) -> Option<InlineLink<'t>>
+    where
+        'a: 't,
+    {
+        let text_end = link_text_ends.get(text, start)?;
+        if let Some(link) = inline_link(text, start, text_end) {
+            return Some(link);
         }
 
-        let paren_end = find_delimiter_simd(text, b')', bracket_end + 2)?;
+        let bytes = text.as_bytes();
+        let link_text = &text[(start + 1)..text_end];
 
-        let link_text = &text[(start + 1)..bracket_end];
-        let url = &text[(bracket_end + 2)..paren_end];
+        // A shortcut reference is only one if no label follows, even one
+        // that is not defined
+        let (label, end) = match bytes.get(text_end + 1) {
+            Some(b'[') => match link_label_end(bytes, text_end + 2) {
+                Some(label_end) if label_end == text_end + 2 => (link_text, label_end + 1),
+                Some(label_end) => (&text[(text_end + 2)..label_end], label_end + 1),
+                None => (link_text, text_end + 1),
+            },
+            _ => (link_text, text_end + 1),
+        };
 
-        output.push_str("<a href=\"");
-        html_escape_simd_into(url, output);
-        output.push_str("\">");
-        html_escape_simd_into(link_text, output);
-        output.push_str("</a>");
+        let definition = self.link_definition_of(label)?;
+        Some(InlineLink {
+            text: link_text,
+            dest_url: definition.dest_url,
+            title: definition.title,
+            len: end - start,
+        })
+    }
 
-        Some(paren_end - start + 1)
+    fn link_definition_of(&self, label: &str) -> Option<LinkDefinition<'a>> {
+        let definitions = self.definitions.borrow();
+        if label.len() > 999 || (definitions.is_empty() && self.seeded.is_empty()) {
+            return None;
+        }
+        let label = normalize_label(label);
+        definitions
+            .get(&label)
+            .or_else(|| self.seeded.get(&label))
+            .cloned()
     }
 }
 
-// Legacy function removed - all code now uses html_escape_simd_into directly
+/// Pushes the events of an autolink whose text starts at `start`, returning
+/// the length of the text.
+fn push_autolink<'t>(
+    text: &'t str,
+    start: usize,
+    autolink: Autolink,
+    events: &mut Vec<Event<'t>>,
+) -> usize {
+    let (len, scheme) = autolink.len_and_scheme();
+    let shown = &text[start..start + len];
+    let dest_url = if scheme.is_empty() {
+        Cow::Borrowed(shown)
+    } else {
+        Cow::Owned(format!("{scheme}{shown}"))
+    };
+    let tag = Tag::Link {
+        dest_url,
+        title: Cow::Borrowed(""),
+    };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    events.push(Event::Start(tag.clone()));
+    events.push(Event::Text(Cow::Borrowed(shown)));
+    events.push(Event::End(tag));
+    len
+}
 
-    #[test]
-    fn test_headers() {
-        let parser = MarkdownParser::new("# Header 1\n## Header 2\n### Header 3");
+/// The parts of an inline link `[text](destination "title")`.
+struct InlineLink<'t> {
+    text: &'t str,
+    dest_url: Cow<'t, str>,
+    title: Cow<'t, str>,
+    /// Length of the whole link, from `[` to `)`
+    len: usize,
+}
+
+/// Parses the inline link starting at the `[` at `start`, whose text is
+/// closed by the `]` at `text_end`.
+fn inline_link(text: &str, start: usize, text_end: usize) -> Option<InlineLink<'_>> {
+    let bytes = text.as_bytes();
+    if bytes.get(text_end + 1) != Some(&b'(') {
+        return None;
+    }
+
+    let mut i = skip_link_whitespace(bytes, text_end + 2);
+    let (dest_url, dest_end) = link_destination(text, i)?;
+    i = skip_link_whitespace(bytes, dest_end);
+
+    // A title has to be separated from the destination
+    let mut title = Cow::Borrowed("");
+    if i > dest_end {
+        if let Some((parsed, title_end)) = link_title(text, i) {
+            title = parsed;
+            i = skip_link_whitespace(bytes, title_end);
+        }
+    }
+
+    if bytes.get(i) != Some(&b')') {
+        return None;
+    }
+    Some(InlineLink {
+        text: &text[(start + 1)..text_end],
+        dest_url,
+        title,
+        len: i + 1 - start,
+    })
+}
+
+/// The `]` closing each `[` of a text, found in a single pass so that trying
+/// a link at every `[` does not scan the rest of the text again.
+struct LinkTextEnds(HashMap<usize, Option<usize>>);
+
+impl LinkTextEnds {
+    fn new(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let mut ends = HashMap::new();
+        let mut open = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 1,
+                b'`' => {
+                    i = code_span(text, i).map_or(i + backtick_run(bytes, i), |span| span.end);
+                    continue;
+                }
+                b'[' => {
+                    open.push(i);
+                    ends.insert(i, None);
+                }
+                b']' => {
+                    if let Some(start) = open.pop() {
+                        ends.insert(start, Some(i));
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        Self(ends)
+    }
+
+    /// Finds the `]` closing the link text opened by the `[` at `start`.
+    ///
+    /// A `[` the pass skipped, inside a code span that another construct
+    /// overlaps, is looked up on its own.
+    fn get(&self, text: &str, start: usize) -> Option<usize> {
+        match self.0.get(&start) {
+            Some(&end) => end,
+            None => link_text_end(text, start + 1),
+        }
+    }
+}
+
+/// Finds the `]` closing link text that starts at `start`. Brackets inside the
+/// text have to be balanced or backslash-escaped.
+fn link_text_end(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            // Code spans bind more tightly than brackets
+            b'`' => {
+                i = code_span(text, i).map_or(i + backtick_run(bytes, i), |span| span.end);
+                continue;
+            }
+            b'[' => depth += 1,
+            b']' if depth == 0 => return Some(i),
+            b']' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// A code span found by [`code_span`].
+struct CodeSpan {
+    /// The text between the backtick runs
+    content: std::ops::Range<usize>,
+    /// Where the closing run ends
+    end: usize,
+}
+
+/// Finds the code span opened by the run of backticks at `start`, which is
+/// closed by the next run of the same length.
+fn code_span(text: &str, start: usize) -> Option<CodeSpan> {
+    let bytes = text.as_bytes();
+    let len = backtick_run(bytes, start);
+    let mut search = start + len;
+    while let Some(close) = find_delimiter_simd(text, b'`', search) {
+        let close_len = backtick_run(bytes, close);
+        if close_len == len {
+            return Some(CodeSpan {
+                content: (start + len)..close,
+                end: close + len,
+            });
+        }
+        search = close + close_len;
+    }
+    None
+}
+
+fn backtick_run(bytes: &[u8], start: usize) -> usize {
+    bytes[start..].iter().take_while(|&&b| b == b'`').count()
+}
+
+/// Turns the line endings inside a code span into spaces, and strips one
+/// space from both ends if there is one at each end, so that a span can
+/// start or end with a backtick: `` `` `a` `` ``.
+fn code_span_content(content: &str) -> Cow<'_, str> {
+    let content = if content.contains('\n') {
+        let mut joined = String::with_capacity(content.len());
+        for (n, line) in content.split('\n').enumerate() {
+            let line = if n > 0 {
+                joined.push(' ');
+                line.trim_start_matches([' ', '\t'])
+            } else {
+                line
+            };
+            joined.push_str(line.strip_suffix('\r').unwrap_or(line));
+        }
+        Cow::Owned(joined)
+    } else {
+        Cow::Borrowed(content)
+    };
+
+    let padded = content.len() >= 2
+        && content.starts_with(' ')
+        && content.ends_with(' ')
+        && !content.bytes().all(|b| b == b' ');
+    if !padded {
+        return content;
+    }
+    match content {
+        Cow::Borrowed(content) => Cow::Borrowed(&content[1..content.len() - 1]),
+        Cow::Owned(content) => Cow::Owned(content[1..content.len() - 1].to_string()),
+    }
+}
+
+/// Finds the `]` closing a link label that starts at `start`. Labels may not
+/// contain unescaped brackets and are at most 999 characters long.
+fn link_label_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start;
+    while i < bytes.len() && i - start <= 999 {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'[' => return None,
+            b']' => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Normalizes a link label for matching: case-folded, with leading and
+/// trailing whitespace removed and inner runs collapsed into one space.
+pub(crate) fn normalize_label(label: &str) -> String {
+    let collapsed = label.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.is_ascii() {
+        collapsed.to_ascii_lowercase()
+    } else {
+        // Lowercasing first folds e.g. `ẞ` to `ß`, which uppercases to `SS`
+        collapsed.to_lowercase().to_uppercase().to_lowercase()
+    }
+}
+
+/// Recognises `[^label]` at the start of `text`, returning the label. Labels
+/// have no whitespace or brackets.
+fn footnote_label(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix("[^")?;
+    let len = rest
+        .bytes()
+        .take(1000)
+        .position(|b| b == b']' || b == b'[' || b.is_ascii_whitespace())?;
+    (len > 0 && rest.as_bytes()[len] == b']').then(|| &rest[..len])
+}
+
+/// Recognises the first line of a footnote definition, `[^label]: text`,
+/// returning the label and the text.
+fn footnote_definition(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start_matches(' ');
+    let label = footnote_label(line)?;
+    let rest = line[(label.len() + 3)..].strip_prefix(':')?;
+    Some((label, rest.trim_start_matches([' ', '\t'])))
+}
+
+/// Parses a link reference definition `[label]: destination "title"` at the
+/// start of `text`, returning the raw label, the definition and the length
+/// up to and including the line ending after it.
+fn link_reference_definition(text: &str) -> Option<(&str, LinkDefinition<'_>, usize)> {
+    let bytes = text.as_bytes();
+    if bytes.first() != Some(&b'[') {
+        return None;
+    }
+    let label_end = link_label_end(bytes, 1)?;
+    let label = &text[1..label_end];
+    if label.trim().is_empty() || bytes.get(label_end + 1) != Some(&b':') {
+        return None;
+    }
+
+    let dest_start = skip_link_whitespace(bytes, label_end + 2);
+    let (dest_url, dest_end) = link_destination(text, dest_start)?;
+    if dest_end == dest_start {
+        return None;
+    }
+
+    // Only whitespace may follow on the line
+    let line_end = |mut i: usize| {
+        while matches!(bytes.get(i), Some(b' ' | b'\t')) {
+            i += 1;
+        }
+        match bytes.get(i) {
+            None => Some(i),
+            Some(b'\n') => Some(i + 1),
+            Some(_) => None,
+        }
+    };
+
+    let title_start = skip_link_whitespace(bytes, dest_end);
+    if title_start > dest_end {
+        if let Some((title, end)) = link_title(text, title_start)
+            .and_then(|(title, title_end)| line_end(title_end).map(|end| (title, end)))
+        {
+            return Some((label, LinkDefinition { dest_url, title }, end));
+        }
+    }
+
+    // A title followed by more text on its line is not part of the definition,
+    // which then ends with the destination if that is on a line of its own
+    let end = line_end(dest_end)?;
+    let title = Cow::Borrowed("");
+    Some((label, LinkDefinition { dest_url, title }, end))
+}
+
+/// Skips spaces, tabs and at most one line ending.
+fn skip_link_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    let mut newline = false;
+    while let Some(&b) = bytes.get(i) {
+        match b {
+            b' ' | b'\t' => {}
+            b'\n' if !newline => newline = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    i
+}
+
+/// How deep parentheses can nest in a link destination.
+const MAX_LINK_PAREN_DEPTH: usize = 32;
+
+/// Parses a link destination at `start`, either enclosed in `<...>`, which
+/// may contain spaces, or a run of non-space characters with balanced
+/// parentheses. Returns the unescaped destination and where it ends.
+///
+/// Like the reference implementation, parentheses nest at most 32 deep, so
+/// an unclosed `(` at every `](` cannot make the search quadratic.
+fn link_destination(text: &str, start: usize) -> Option<(Cow<'_, str>, usize)> {
+    let bytes = text.as_bytes();
+
+    if bytes.get(start) == Some(&b'<') {
+        let mut i = start + 1;
+        loop {
+            match *bytes.get(i)? {
+                b'>' => return Some((unescape(&text[(start + 1)..i]), i + 1)),
+                b'<' | b'\n' => return None,
+                b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => i += 2,
+                _ => i += 1,
+            }
+        }
+    }
+
+    let mut depth = 0usize;
+    let mut i = start;
+    while let Some(&b) = bytes.get(i) {
+        match b {
+            b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => i += 1,
+            b'(' if depth == MAX_LINK_PAREN_DEPTH => return None,
+            b'(' => depth += 1,
+            b')' if depth == 0 => break,
+            b')' => depth -= 1,
+            _ if b.is_ascii_whitespace() || b.is_ascii_control() => break,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    (depth == 0).then(|| (unescape(&text[start..i]), i))
+}
+
+/// Parses a link title in `"..."`, `'...'` or `(...)` at `start`, returning it
+/// unescaped along with where it ends.
+fn link_title(text: &str, start: usize) -> Option<(Cow<'_, str>, usize)> {
+    let bytes = text.as_bytes();
+    let close = match *bytes.get(start)? {
+        b'"' => b'"',
+        b'\'' => b'\'',
+        b'(' => b')',
+        _ => return None,
+    };
+
+    let mut i = start + 1;
+    loop {
+        match *bytes.get(i)? {
+            b if b == close => return Some((unescape(&text[(start + 1)..i]), i + 1)),
+            b'(' if close == b')' => return None,
+            b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => i += 2,
+            // Titles may span lines, but not blank ones
+            b'\n'
+                if text[(i + 1)..]
+                    .trim_start_matches([' ', '\t'])
+                    .starts_with('\n') =>
+            {
+                return None
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Resolves backslash escapes of ASCII punctuation and character references,
+/// borrowing `text` when it has neither.
+fn unescape(text: &str) -> Cow<'_, str> {
+    let bytes = text.as_bytes();
+    if !bytes.iter().any(|&b| b == b'\\' || b == b'&') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut unescaped = String::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => {
+                unescaped.push_str(&text[start..i]);
+                start = i + 1;
+                i += 2;
+            }
+            b'&' => match scan_entity(text, i) {
+                Some((value, len)) => {
+                    unescaped.push_str(&text[start..i]);
+                    unescaped.push_str(&value);
+                    i += len;
+                    start = i;
+                }
+                None => i += 1,
+            },
+            _ => i += 1,
+        }
+    }
+
+    if start == 0 {
+        return Cow::Borrowed(text);
+    }
+    unescaped.push_str(&text[start..]);
+    Cow::Owned(unescaped)
+}
+
+/// Iterator over the [`Event`]s of a document, created by
+/// [`MarkdownParser::events`].
+pub struct Events<'p, 'a> {
+    parser: &'p MarkdownParser<'a>,
+    lines: Vec<&'a str>,
+    position: usize,
+    pending: std::vec::IntoIter<Event<'a>>,
+    /// Set once the footnotes following the last block have been parsed
+    footnotes_done: bool,
+}
+
+impl<'a> Iterator for Events<'_, 'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        loop {
+            if let Some(event) = self.pending.next() {
+                return Some(event);
+            }
+
+            while self.position < self.lines.len() && self.lines[self.position].trim().is_empty() {
+                self.position += 1;
+            }
+            if self.position >= self.lines.len() {
+                // The footnotes follow the last block
+                if self.footnotes_done || !self.parser.options.footnotes {
+                    return None;
+                }
+                self.footnotes_done = true;
+                let mut events = Vec::new();
+                self.parser.parse_footnotes(&mut events);
+                self.pending = events.into_iter();
+                continue;
+            }
+
+            let mut events = Vec::new();
+            self.position += self
+                .parser
+                .parse_block(&self.lines[self.position..], &mut events);
+            self.pending = events.into_iter();
+        }
+    }
+}
+
+/// The content lines of a container block as they are collected, tracking
+/// whether a code fence among them is still open, so that a lazy continuation
+/// line can be checked without rescanning the lines before it.
+#[derive(Default)]
+struct ContainerLines<'l> {
+    lines: Vec<&'l str>,
+    open_fence: Option<CodeFence<'l>>,
+}
+
+impl<'l> ContainerLines<'l> {
+    fn push(&mut self, line: &'l str) {
+        self.open_fence = match self.open_fence.take() {
+            Some(fence) if fence.is_closed_by(line) => None,
+            Some(fence) => Some(fence),
+            None => code_fence(line),
+        };
+        self.lines.push(line);
+    }
+}
+
+/// The opening line of a fenced code block.
+struct CodeFence<'a> {
+    /// `` ` `` or `~`
+    marker: u8,
+    /// Length of the marker run, at least 3
+    len: usize,
+    /// Spaces before the fence, removed from the content lines too
+    indent: usize,
+    /// The trimmed text after the fence, e.g. `rust` or `rust {linenos}`
+    info: &'a str,
+}
+
+impl CodeFence<'_> {
+    /// A closing fence uses the same marker, at least as many times, and has
+    /// nothing after it but whitespace.
+    fn is_closed_by(&self, line: &str) -> bool {
+        let trimmed = line.trim_start_matches(' ');
+        if line.len() - trimmed.len() > 3 {
+            return false;
+        }
+        let len = trimmed.bytes().take_while(|&b| b == self.marker).count();
+        len >= self.len && trimmed[len..].trim().is_empty()
+    }
+}
+
+/// Recognises an opening code fence: three or more backticks or tildes,
+/// indented by at most three spaces.
+fn code_fence(line: &str) -> Option<CodeFence<'_>> {
+    let trimmed = line.trim_start_matches(' ');
+    let indent = line.len() - trimmed.len();
+    let marker = *trimmed.as_bytes().first()?;
+    if indent > 3 || !matches!(marker, b'`' | b'~') {
+        return None;
+    }
+    let len = trimmed.bytes().take_while(|&b| b == marker).count();
+    let info = trimmed[len..].trim();
+    // Backticks in the info string would make it a code span instead
+    if len < 3 || (marker == b'`' && info.contains('`')) {
+        return None;
+    }
+    Some(CodeFence {
+        marker,
+        len,
+        indent,
+        info,
+    })
+}
+
+struct ListMarker {
+    /// Bullet character for unordered lists, `.` or `)` for ordered ones
+    marker: u8,
+    /// Start number of an ordered list
+    start: Option<u64>,
+    /// Column at which the item's content begins
+    content_offset: usize,
+    /// Byte offset of the content on the marker's own line
+    content_start: usize,
+}
+
+/// Splits a table row into trimmed cells. Leading and trailing pipes are
+/// optional, and `\|` inside a cell stands for a literal pipe.
+fn split_table_row(line: &str) -> Vec<Cow<'_, str>> {
+    let mut row = line.trim();
+    row = row.strip_prefix('|').unwrap_or(row);
+    if row.ends_with('|') && !row.ends_with("\\|") {
+        row = &row[..row.len() - 1];
+    }
+
+    let bytes = row.as_bytes();
+    let mut cells = Vec::new();
+    let mut cell_start = 0;
+    let mut escaped_pipe = false;
+    let mut i = 0;
+
+    while i <= bytes.len() {
+        match bytes.get(i) {
+            Some(b'\\') if bytes.get(i + 1) == Some(&b'|') => {
+                escaped_pipe = true;
+                i += 2;
+                continue;
+            }
+            Some(b'|') | None => {
+                let cell = row[cell_start..i].trim();
+                cells.push(if escaped_pipe {
+                    Cow::Owned(cell.replace("\\|", "|"))
+                } else {
+                    Cow::Borrowed(cell)
+                });
+                cell_start = i + 1;
+                escaped_pipe = false;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    cells
+}
+
+#[inline]
+fn indentation(line: &str) -> usize {
+    line.bytes().take_while(|&b| b == b' ').count()
+}
+
+/// Removes up to `columns` columns of leading whitespace. A tab always ends
+/// on a multiple of four columns, so stripping four never splits one.
+fn strip_columns(line: &str, columns: usize) -> &str {
+    let mut width = 0;
+    for (i, byte) in line.bytes().enumerate() {
+        width += match byte {
+            b' ' => 1,
+            b'\t' => 4 - width % 4,
+            _ => return &line[i..],
+        };
+        if width >= columns {
+            return &line[i + 1..];
+        }
+    }
+    ""
+}
+
+/// Returns the heading level a setext underline (`===` or `---`, indented by
+/// at most three spaces) gives the paragraph above it.
+fn setext_underline(line: &str) -> Option<usize> {
+    let trimmed = line.trim_end();
+    let underline = trimmed.trim_start_matches(' ');
+    if trimmed.len() - underline.len() > 3 {
+        return None;
+    }
+    let level = match underline.as_bytes().first()? {
+        b'=' => 1,
+        b'-' => 2,
+        _ => return None,
+    };
+    let marker = underline.as_bytes()[0];
+    underline.bytes().all(|b| b == marker).then_some(level)
+}
+
+/// Recognises the `[ ]`, `[x]` or `[X]` that starts a task list item, followed
+/// by whitespace, returning whether it is checked and the rest of the line.
+fn task_marker(line: &str) -> Option<(bool, &str)> {
+    let checked = match line.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    let rest = &line[3..];
+    rest.starts_with([' ', '\t']).then(|| (checked, &rest[1..]))
+}
+
+/// An indented line can't start a code block inside a paragraph, so it
+/// continues the paragraph like plain text does.
+fn continues_paragraph(line: &str) -> bool {
+    matches!(
+        detect_line_type_simd(line),
+        LineType::Paragraph | LineType::IndentedCode
+    )
+}
+
+// Legacy function removed - all code now uses html_escape_simd_into directly
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers() {
+        let parser = MarkdownParser::new("# Header 1\n## Header 2\n### Header 3");
         let result = parser.parse();
         assert!(result.contains("<h1>Header 1</h1>"));
         assert!(result.contains("<h2>Header 2</h2>"));
@@ -374,6 +1887,35 @@ mod tests {
         assert!(result.contains("<em>italic</em>"));
     }
 
+    #[test]
+    fn test_emphasis_delimiter_runs() {
+        let cases = [
+            ("snake_case_names", "snake_case_names"),
+            ("2 * 3 * 4", "2 * 3 * 4"),
+            ("***both***", "<em><strong>both</strong></em>"),
+            ("*a **b** c*", "<em>a <strong>b</strong> c</em>"),
+            ("**a*b*c**", "<strong>a<em>b</em>c</strong>"),
+            ("_a_b_ and a*b*c", "<em>a_b</em> and a<em>b</em>c"),
+            ("*a**b*", "<em>a**b</em>"),
+            ("**unclosed *em*", "**unclosed <em>em</em>"),
+            (
+                "*a <b> &amp; `c`*",
+                "<em>a &lt;b&gt; &amp; <code>c</code></em>",
+            ),
+            (
+                "*[link](/u)* and [*a*](/b)",
+                "<em><a href=\"/u\">link</a></em> and <a href=\"/b\"><em>a</em></a>",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                MarkdownParser::new(input).parse(),
+                format!("<p>{expected}</p>\n"),
+                "{input:?}"
+            );
+        }
+    }
+
     #[test]
     fn test_inline_code() {
         let parser = MarkdownParser::new("Use `code` for inline code");
@@ -381,6 +1923,48 @@ mod tests {
         assert!(result.contains("<code>code</code>"));
     }
 
+    #[test]
+    fn test_code_span_delimiters() {
+        let cases = [
+            ("``a `tick` inside``", "<code>a `tick` inside</code>"),
+            ("`` `a` ``", "<code>`a`</code>"),
+            ("` `` `", "<code>``</code>"),
+            ("`  a  `", "<code> a </code>"),
+            ("`  `", "<code>  </code>"),
+            ("`a\n  b\r\nc`", "<code>a b c</code>"),
+            ("```a``", "```a``"),
+            ("`*not em*` *em*", "<code>*not em*</code> <em>em</em>"),
+            ("[a `]` b](/u)", "<a href=\"/u\">a <code>]</code> b</a>"),
+            ("`a\\`b", "<code>a\\</code>b"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                MarkdownParser::new(input).parse(),
+                format!("<p>{expected}</p>\n"),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_code_span_unmatched_runs_and_padding() {
+        // A run only closes a span of the same length; unclosed runs stay text
+        let result = MarkdownParser::new("`a``\n\n``a`b`\n\n```a`b```\n\n`a` `` b").parse();
+        assert_eq!(
+            result,
+            "<p>`a``</p>\n<p>``a<code>b</code></p>\n<p><code>a`b</code></p>\n<p><code>a</code> `` b</p>\n"
+        );
+
+        // One space comes off each end only when both ends have one, and only
+        // spaces and line endings count
+        let result = MarkdownParser::new("` a` `a ` `  a  ` `\ta\t` `\na\n` `   `").parse();
+        assert_eq!(
+            result,
+            "<p><code> a</code> <code>a </code> <code> a </code> <code>\ta\t</code> \
+             <code>a</code> <code>   </code></p>\n"
+        );
+    }
+
     #[test]
     fn test_links() {
         let parser = MarkdownParser::new("This is a [link](https://example.com)");
@@ -388,6 +1972,379 @@ mod tests {
         assert!(result.contains("<a href=\"https://example.com\">link</a>"));
     }
 
+    #[test]
+    fn test_link_destinations_and_titles() {
+        let cases = [
+            (
+                "[x](https://en.wikipedia.org/wiki/Rust_(language))",
+                "<a href=\"https://en.wikipedia.org/wiki/Rust_(language)\">x</a>",
+            ),
+            (
+                "[x](url \"Title\")",
+                "<a href=\"url\" title=\"Title\">x</a>",
+            ),
+            (
+                "[x](url 'a \"b\"')",
+                "<a href=\"url\" title=\"a &quot;b&quot;\">x</a>",
+            ),
+            ("[x](url (paren))", "<a href=\"url\" title=\"paren\">x</a>"),
+            ("[x](<my page.html>)", "<a href=\"my%20page.html\">x</a>"),
+            (
+                "[x](a\\)b \"c\\\"d\")",
+                "<a href=\"a)b\" title=\"c&quot;d\">x</a>",
+            ),
+            ("[x]()", "<a href=\"\">x</a>"),
+            ("[x](caf\u{e9})", "<a href=\"caf%C3%A9\">x</a>"),
+            ("[a [b] c](/u)", "<a href=\"/u\">a [b] c</a>"),
+            ("[x](<a\nb>)", "[x](&lt;a\nb&gt;)"),
+            ("[x](url \"t\" junk)", "[x](url &quot;t&quot; junk)"),
+            ("[x](a(b)", "[x](a(b)"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                MarkdownParser::new(input).parse(),
+                format!("<p>{expected}</p>\n"),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_link_text_is_inline_content() {
+        let result = MarkdownParser::new("[![badge](b.svg)](https://ci) [*docs*](/d)").parse();
+        assert_eq!(
+            result,
+            "<p><a href=\"https://ci\"><img src=\"b.svg\" alt=\"badge\"></a> <a href=\"/d\"><em>docs</em></a></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_many_unclosed_brackets() {
+        let input = "[".repeat(50_000) + "![x](/a) [b]](/c)";
+        let result = MarkdownParser::new(&input).parse();
+        assert!(result.ends_with("[<a href=\"/c\"><img src=\"/a\" alt=\"x\"> [b]</a></p>\n"));
+
+        // The `[` after the link is inside the code span seen from the start
+        let result = MarkdownParser::new("[a](`) [b](/u)`").parse();
+        assert_eq!(
+            result,
+            "<p><a href=\"%60\">a</a> <a href=\"/u\">b</a>`</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_many_unclosed_link_destinations() {
+        let input = "[a](".repeat(20_000);
+        let result = MarkdownParser::new(&input).parse();
+        assert_eq!(result, format!("<p>{input}</p>\n"));
+
+        let nested = |depth| format!("[a](x{}{})", "(".repeat(depth), ")".repeat(depth));
+        let result = MarkdownParser::new(&nested(32)).parse();
+        assert!(result.starts_with("<p><a href="));
+        let result = MarkdownParser::new(&nested(33)).parse();
+        assert!(result.starts_with("<p>[a](x((("));
+    }
+
+    #[test]
+    fn test_reference_links() {
+        let input = "See [the guide][Guide], [guide][] and [GUIDE].\n\
+                     Also [not defined] and ![logo][].\n\n\
+                     [guide]: https://example.com/guide \"The  Guide\"\n\
+                     [Logo]:\n  <logo image.png>\n  'Our logo'\n\
+                     [guide]: https://example.com/ignored\n";
+        let result = MarkdownParser::new(input).parse();
+        let link = "<a href=\"https://example.com/guide\" title=\"The  Guide\">";
+        assert_eq!(
+            result,
+            format!(
+                "<p>See {link}the guide</a>, {link}guide</a> and {link}GUIDE</a>.\n\
+                 Also [not defined] and <img src=\"logo%20image.png\" alt=\"logo\" title=\"Our logo\">.</p>\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_link_reference_definitions() {
+        // Definitions are removed from the paragraphs they start, in any block
+        let input = "[a]: /a\n[b]: /b 'B'\ntext [a]\n\n> [c]: /c\n\n- [c]\n\n[x]: /x \"t\" junk\n";
+        let result = MarkdownParser::new(input).parse();
+        assert_eq!(
+            result,
+            "<p>text <a href=\"/a\">a</a></p>\n<blockquote>\n</blockquote>\n\
+             <ul>\n  <li><a href=\"/c\">c</a></li>\n</ul>\n<p>[x]: /x &quot;t&quot; junk</p>\n"
+        );
+
+        let parser = MarkdownParser::new(input);
+        let definitions = parser.link_definitions();
+        assert_eq!(definitions.len(), 3);
+        assert_eq!(
+            parser.link_definition(" B "),
+            Some(LinkDefinition {
+                dest_url: "/b".into(),
+                title: "B".into()
+            })
+        );
+        assert_eq!(parser.link_definition("x"), None);
+    }
+
+    #[test]
+    fn test_seeded_link_definitions() {
+        let seeded = |url: &'static str| LinkDefinition {
+            dest_url: url.into(),
+            title: "".into(),
+        };
+        let parser = MarkdownParser::new("[Rust] and [Crates]\n\n[rust]: /local-rust")
+            .with_link_definition("rust", seeded("https://rust-lang.org"))
+            .with_link_definition("crates", seeded("https://crates.io"));
+        assert_eq!(
+            parser.parse(),
+            "<p><a href=\"/local-rust\">Rust</a> and <a href=\"https://crates.io\">Crates</a></p>\n"
+        );
+        assert_eq!(parser.link_definitions()["rust"], seeded("/local-rust"));
+    }
+
+    #[test]
+    fn test_autolinks() {
+        let result =
+            MarkdownParser::new("<https://example.com/a b> <https://x.org?q=1&r> <me@example.com>")
+                .parse();
+        assert_eq!(
+            result,
+            "<p>&lt;https://example.com/a b&gt; <a href=\"https://x.org?q=1&amp;r\">https://x.org?q=1&amp;r</a> \
+             <a href=\"mailto:me@example.com\">me@example.com</a></p>\n"
+        );
+
+        // Autolinks take precedence over raw HTML
+        let options = ParserOptions::new().raw_html(true);
+        let result = MarkdownParser::with_options("<b>a</b> <http://b.c>", options).parse();
+        assert_eq!(
+            result,
+            "<p><b>a</b> <a href=\"http://b.c\">http://b.c</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_inline_extensions() {
+        let input = "~~old~~ ==new== x^2^ H~2~O";
+        assert_eq!(
+            MarkdownParser::new(input).parse(),
+            "<p>~~old~~ ==new== x^2^ H~2~O</p>\n"
+        );
+
+        let options = ParserOptions::new()
+            .strikethrough(true)
+            .mark(true)
+            .superscript(true)
+            .subscript(true);
+        let parse = |input| MarkdownParser::with_options(input, options.clone()).parse();
+        assert_eq!(
+            parse(input),
+            "<p><del>old</del> <mark>new</mark> x<sup>2</sup> H<sub>2</sub>O</p>\n"
+        );
+        assert_eq!(
+            parse("**~~bold *strike*~~** ==*a*=="),
+            "<p><strong><del>bold <em>strike</em></del></strong> <mark><em>a</em></mark></p>\n"
+        );
+        // Runs pair only with the same length, and follow the flanking rules
+        assert_eq!(parse("~~a~ b ~~~c~~~"), "<p>~~a~ b ~~~c~~~</p>\n");
+        assert_eq!(parse("a ~~ b ~~ c ^ d ^"), "<p>a ~~ b ~~ c ^ d ^</p>\n");
+        assert_eq!(parse("===x=== \\^y^"), "<p>===x=== ^y^</p>\n");
+
+        // Each extension is toggled on its own
+        let options = ParserOptions::new().strikethrough(true);
+        assert_eq!(
+            MarkdownParser::with_options("~~a~~ ~b~", options).parse(),
+            "<p><del>a</del> ~b~</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_strikethrough_inside_words_and_with_subscript() {
+        let options = ParserOptions::new().strikethrough(true);
+        let parse = |input| MarkdownParser::with_options(input, options.clone()).parse();
+        // `~` runs may open and close inside words, unlike `_`
+        assert_eq!(
+            parse("snake~~case~~word a~~b~~ ~~c~~d"),
+            "<p>snake<del>case</del>word a<del>b</del> <del>c</del>d</p>\n"
+        );
+
+        let options = options.subscript(true);
+        let parse = |input| MarkdownParser::with_options(input, options.clone()).parse();
+        assert_eq!(
+            parse("~~a ~b~ c~~ ~a~~b~~c~ H~2~O~~x~~"),
+            "<p><del>a <sub>b</sub> c</del> <sub>a<del>b</del>c</sub> H<sub>2</sub>O<del>x</del></p>\n"
+        );
+        // A run only closes one of the same length, and `~~~` is neither
+        assert_eq!(parse("~~a~b~~"), "<p><del>a~b</del></p>\n");
+        assert_eq!(parse("~a~~"), "<p>~a~~</p>\n");
+        assert_eq!(parse("~~del~~~sub~"), "<p>~~del~~~sub~</p>\n");
+    }
+
+    #[test]
+    fn test_footnote_repeats_undefined_labels_and_nested_definitions() {
+        let input = "a[^n] b[^n] c[^n] d[^missing]\n\n\
+                     - item\n\n  [^n]: in a list\n\n\
+                     > [^q]: quoted\n\n\
+                     e[^q]";
+        let parser = MarkdownParser::with_options(input, ParserOptions::new().footnotes(true));
+        let backref = |id: &str, number: usize, label: &str| {
+            format!(
+                "<a href=\"#{id}\" class=\"footnote-backref\" aria-label=\"Back to reference {number}\">{label}</a>"
+            )
+        };
+        // Definitions inside containers count for the whole document and
+        // leave nothing behind where they stand
+        let expected = format!(
+            "<p>a<sup><a href=\"#fn-1\" id=\"fnref-1\">1</a></sup> \
+             b<sup><a href=\"#fn-1\" id=\"fnref-1-2\">1</a></sup> \
+             c<sup><a href=\"#fn-1\" id=\"fnref-1-3\">1</a></sup> d[^missing]</p>\n\
+             <ul>\n  <li>\n<p>item</p>\n</li>\n</ul>\n<blockquote>\n</blockquote>\n\
+             <p>e<sup><a href=\"#fn-2\" id=\"fnref-2\">2</a></sup></p>\n\
+             <section class=\"footnotes\">\n<ol>\n\
+             <li id=\"fn-1\">\n<p>in a list {} {} {}</p>\n</li>\n\
+             <li id=\"fn-2\">\n<p>quoted {}</p>\n</li>\n\
+             </ol>\n</section>\n",
+            backref("fnref-1", 1, "\u{21a9}"),
+            backref("fnref-1-2", 1, "\u{21a9}<sup>2</sup>"),
+            backref("fnref-1-3", 1, "\u{21a9}<sup>3</sup>"),
+            backref("fnref-2", 2, "\u{21a9}"),
+        );
+        assert_eq!(parser.parse(), expected);
+
+        let occurrences: Vec<usize> = parser
+            .events()
+            .filter_map(|event| match event {
+                Event::FootnoteReference { occurrence, .. } => Some(occurrence),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(occurrences, [1, 2, 3, 1]);
+
+        // A reference to an undefined label is text, even with no definitions
+        assert_eq!(
+            MarkdownParser::with_options("x[^1]", ParserOptions::new().footnotes(true)).parse(),
+            "<p>x[^1]</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let input = "Claim[^a], more[^B] and again[^a]. Not [^none] nor [^ a].\n\n\
+                     [^b]: Second[^c].\n\
+                     [^a]: First\n    lazy\nline\n\n    > quoted\n\n\
+                     [^c]: Nested\n\
+                     [^unused]: dropped\n\n\
+                     After.";
+        // Without footnotes, a definition is a link reference definition
+        assert_eq!(
+            MarkdownParser::new("a[^1]\n\n[^1]: note").parse(),
+            "<p>a<a href=\"note\">^1</a></p>\n"
+        );
+
+        let parser = MarkdownParser::with_options(input, ParserOptions::new().footnotes(true));
+        let backref = |id: &str, number: usize, label: &str| {
+            format!(
+                "<a href=\"#{id}\" class=\"footnote-backref\" aria-label=\"Back to reference {number}\">{label}</a>"
+            )
+        };
+        let expected = format!(
+            "<p>Claim<sup><a href=\"#fn-1\" id=\"fnref-1\">1</a></sup>, \
+             more<sup><a href=\"#fn-2\" id=\"fnref-2\">2</a></sup> and \
+             again<sup><a href=\"#fn-1\" id=\"fnref-1-2\">1</a></sup>. Not [^none] nor [^ a].</p>\n\
+             <p>After.</p>\n\
+             <section class=\"footnotes\">\n<ol>\n\
+             <li id=\"fn-1\">\n<p>First\nlazy\nline</p>\n<blockquote>\n<p>quoted</p>\n</blockquote>\n\
+             {} {}\n</li>\n\
+             <li id=\"fn-2\">\n<p>Second<sup><a href=\"#fn-3\" id=\"fnref-3\">3</a></sup>. {}</p>\n</li>\n\
+             <li id=\"fn-3\">\n<p>Nested {}</p>\n</li>\n\
+             </ol>\n</section>\n",
+            backref("fnref-1", 1, "\u{21a9}"),
+            backref("fnref-1-2", 1, "\u{21a9}<sup>2</sup>"),
+            backref("fnref-2", 2, "\u{21a9}"),
+            backref("fnref-3", 3, "\u{21a9}"),
+        );
+        assert_eq!(parser.parse(), expected);
+        // Numbering starts over for each rendering
+        assert_eq!(parser.parse(), expected);
+
+        let references: Vec<Event> = parser
+            .events()
+            .filter(|event| matches!(event, Event::FootnoteReference { .. }))
+            .collect();
+        assert_eq!(
+            references[1],
+            Event::FootnoteReference {
+                label: "B".into(),
+                number: 2,
+                occurrence: 1
+            }
+        );
+        assert!(parser.events().any(|event| event
+            == Event::Start(Tag::FootnoteDefinition {
+                number: 1,
+                references: 2
+            })));
+    }
+
+    #[test]
+    fn test_extended_autolinks() {
+        let input = "Visit www.example.com/docs, (https://example.com/a_(b)) or mail me.too@example.co.uk.\n\
+                     Not inside [www.example.com](/x) or mid-wordwww.example.com.";
+        let result = MarkdownParser::new(input).parse();
+        assert!(!result.contains("href=\"http://www"));
+
+        let options = ParserOptions::new().extended_autolinks(true);
+        let result = MarkdownParser::with_options(input, options).parse();
+        assert_eq!(
+            result,
+            "<p>Visit <a href=\"http://www.example.com/docs\">www.example.com/docs</a>, \
+             (<a href=\"https://example.com/a_(b)\">https://example.com/a_(b)</a>) or mail \
+             <a href=\"mailto:me.too@example.co.uk\">me.too@example.co.uk</a>.\n\
+             Not inside <a href=\"/x\">www.example.com</a> or mid-wordwww.example.com.</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_backslash_escapes() {
+        let result =
+            MarkdownParser::new("\\*not italic\\* \\[no link](/x) \\`no code\\` C:\\dir \\\\")
+                .parse();
+        assert_eq!(
+            result,
+            "<p>*not italic* [no link](/x) `no code` C:\\dir \\</p>\n"
+        );
+
+        let result = MarkdownParser::new("[a\\]b](/p\\(1\\) \"say \\\"hi\\\"\")").parse();
+        assert_eq!(
+            result,
+            "<p><a href=\"/p(1)\" title=\"say &quot;hi&quot;\">a]b</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_entity_references() {
+        let result = MarkdownParser::new("&copy; &#169; &#xA9; &amp;lt; &nope; &#0; AT&T").parse();
+        assert_eq!(
+            result,
+            "<p>\u{a9} \u{a9} \u{a9} &amp;lt; &amp;nope; \u{fffd} AT&amp;T</p>\n"
+        );
+
+        // Decoded in destinations, titles and info strings, then re-encoded
+        let result = MarkdownParser::new(
+            "[x](/f&ouml;&ouml; \"&lt;b&gt;\")\n\n```h&#97;skell\nmain = pure ()\n```",
+        )
+        .parse();
+        assert_eq!(
+            result,
+            "<p><a href=\"/f%C3%B6%C3%B6\" title=\"&lt;b&gt;\">x</a></p>\n\
+             <pre><code class=\"language-haskell\">main = pure ()\n</code></pre>\n"
+        );
+
+        // Character references are literal in code
+        let result = MarkdownParser::new("`&copy;`").parse();
+        assert_eq!(result, "<p><code>&amp;copy;</code></p>\n");
+    }
+
     #[test]
     fn test_unordered_list() {
         let parser = MarkdownParser::new("- Item 1\n- Item 2\n- Item 3");
@@ -410,6 +2367,347 @@ mod tests {
         assert!(result.contains("</ol>"));
     }
 
+    #[test]
+    fn test_list_marker_delimiters_and_tabs() {
+        // A `)` list is a different list from a `.` one
+        let result = MarkdownParser::new("1) a\n2) b\n3. c").parse();
+        assert_eq!(
+            result,
+            "<ol>\n  <li>a</li>\n  <li>b</li>\n</ol>\n<ol start=\"3\">\n  <li>c</li>\n</ol>\n"
+        );
+
+        // The tab ends on column four, where the continuation lines start
+        let result = MarkdownParser::new("-\tone\n\n    two\n1.\tthree").parse();
+        assert_eq!(
+            result,
+            "<ul>\n  <li>\n<p>one</p>\n<p>two</p>\n</li>\n</ul>\n<ol>\n  <li>three</li>\n</ol>\n"
+        );
+    }
+
+    #[test]
+    fn test_task_lists() {
+        let input = "- [ ] todo\n- [x] done\n- [X]\tupper\n- [ ]\n- [y] no\n- plain [ ] box";
+        assert_eq!(
+            MarkdownParser::new(input).parse(),
+            "<ul>\n  <li class=\"task-list-item\"><input type=\"checkbox\" disabled> todo</li>\n  \
+             <li class=\"task-list-item\"><input type=\"checkbox\" disabled checked> done</li>\n  \
+             <li class=\"task-list-item\"><input type=\"checkbox\" disabled checked> upper</li>\n  \
+             <li>[ ]</li>\n  <li>[y] no</li>\n  <li>plain [ ] box</li>\n</ul>\n"
+        );
+
+        let open_items = MarkdownParser::new("1. [x] a\n2. [ ] b\n   - [ ] c")
+            .events()
+            .filter(|event| *event == Event::Start(Tag::Item(Some(false))))
+            .count();
+        assert_eq!(open_items, 2);
+
+        let options = ParserOptions::new().xhtml(true);
+        assert_eq!(
+            MarkdownParser::with_options("* [x] *done*", options).parse(),
+            "<ul>\n  <li class=\"task-list-item\"><input type=\"checkbox\" disabled checked /> <em>done</em></li>\n</ul>\n"
+        );
+        let options = ParserOptions::new().task_lists(false);
+        assert_eq!(
+            MarkdownParser::with_options("- [ ] todo", options).parse(),
+            "<ul>\n  <li>[ ] todo</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn test_task_list_markers_need_a_space_and_the_item_start() {
+        // The marker needs whitespace after it
+        assert_eq!(
+            MarkdownParser::new("- [x]done\n- [ ]x").parse(),
+            "<ul>\n  <li>[x]done</li>\n  <li>[ ]x</li>\n</ul>\n"
+        );
+
+        // Any item may be a task, but only its first line can hold the box
+        assert_eq!(
+            MarkdownParser::new("- plain\n- [ ] second\n- [x] [ ] third").parse(),
+            "<ul>\n  <li>plain</li>\n  \
+             <li class=\"task-list-item\"><input type=\"checkbox\" disabled> second</li>\n  \
+             <li class=\"task-list-item\"><input type=\"checkbox\" disabled checked> [ ] third</li>\n</ul>\n"
+        );
+        assert_eq!(
+            MarkdownParser::new("- a\n  [ ] b\n\n  [x] c").parse(),
+            "<ul>\n  <li>\n<p>a\n[ ] b</p>\n<p>[x] c</p>\n</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_loose_lists() {
+        // Each list is parsed once whether it turns out tight or loose, so
+        // nesting depth does not multiply the work
+        let input: String = (0..40)
+            .map(|level| format!("{}- a{level}\n\n", "  ".repeat(level)))
+            .collect();
+        let result = MarkdownParser::new(&input).parse();
+        assert!(result.starts_with("<ul>\n  <li>\n<p>a0</p>\n<ul>\n  <li>\n<p>a1</p>"));
+        assert!(result.contains("<p>a38</p>\n<ul>\n  <li>a39</li>\n</ul>"));
+
+        // A tight list inside a loose one keeps its items' content bare
+        assert_eq!(
+            MarkdownParser::new("- a\n\n  - b\n  - c\n\n  d").parse(),
+            "<ul>\n  <li>\n<p>a</p>\n<ul>\n  <li>b</li>\n  <li>c</li>\n</ul>\n<p>d</p>\n</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_list() {
+        let parser = MarkdownParser::new("- a\n  - b\n  - c\n- d\n  lazy");
+        let result = parser.parse();
+        assert_eq!(
+            result,
+            "<ul>\n  <li>a\n<ul>\n  <li>b</li>\n  <li>c</li>\n</ul>\n</li>\n  <li>d\nlazy</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn test_loose_list_with_multiple_paragraphs() {
+        let parser = MarkdownParser::new("3. first\n\n   more\n4. second");
+        let result = parser.parse();
+        assert_eq!(
+            result,
+            "<ol start=\"3\">\n  <li>\n<p>first</p>\n<p>more</p>\n</li>\n  <li>\n<p>second</p>\n</li>\n</ol>\n"
+        );
+    }
+
+    #[test]
+    fn test_list_item_with_code_block() {
+        let parser = MarkdownParser::new("- item\n  ```\n  code\n  ```\n- next");
+        let result = parser.parse();
+        assert!(result.contains("<li>item\n<pre><code>code\n</code></pre>\n</li>"));
+        assert!(result.contains("<li>next</li>"));
+    }
+
+    #[test]
+    fn test_table() {
+        let input = "| Name | Qty | Note |\n|:-----|:---:|-----:|\n| **a** | 1 | x \\| y |\n| b |";
+        let result = MarkdownParser::new(input).parse();
+        assert_eq!(
+            result,
+            "<table>\n<thead>\n<tr>\n<th style=\"text-align: left\">Name</th>\n\
+             <th style=\"text-align: center\">Qty</th>\n<th style=\"text-align: right\">Note</th>\n\
+             </tr>\n</thead>\n<tbody>\n<tr>\n<td style=\"text-align: left\"><strong>a</strong></td>\n\
+             <td style=\"text-align: center\">1</td>\n<td style=\"text-align: right\">x | y</td>\n\
+             </tr>\n<tr>\n<td style=\"text-align: left\">b</td>\n<td style=\"text-align: center\"></td>\n\
+             <td style=\"text-align: right\"></td>\n</tr>\n</tbody>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn test_table_alignment_class() {
+        let options = ParserOptions::new().table_alignment_class("align-");
+        let result = MarkdownParser::with_options("a | b\n--- | :-:", options).parse();
+        assert_eq!(
+            result,
+            "<table>\n<thead>\n<tr>\n<th>a</th>\n<th class=\"align-center\">b</th>\n</tr>\n</thead>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn test_table_requires_matching_delimiter_row() {
+        let result = MarkdownParser::new("a | b\n--- | --- | ---").parse();
+        assert_eq!(result, "<p>a | b\n--- | --- | ---</p>\n");
+    }
+
+    #[test]
+    fn test_tables_can_be_disabled() {
+        let options = ParserOptions::new().tables(false);
+        let result = MarkdownParser::with_options("a | b\n--- | ---", options).parse();
+        assert_eq!(result, "<p>a | b\n--- | ---</p>\n");
+    }
+
+    #[test]
+    fn test_raw_html_is_escaped_by_default() {
+        let result = MarkdownParser::new("<div>\n*hi*\n</div>\n\n<b>x</b>").parse();
+        assert_eq!(
+            result,
+            "<p>&lt;div&gt;\n<em>hi</em>\n&lt;/div&gt;</p>\n<p>&lt;b&gt;x&lt;/b&gt;</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_raw_html_passthrough() {
+        let options = ParserOptions::new().raw_html(true);
+        let input = "<div class=\"note\">\n*not parsed*\n</div>\n\nSome <b title='x'>bold</b> & <3\n<!-- comment -->";
+        let result = MarkdownParser::with_options(input, options).parse();
+        assert_eq!(
+            result,
+            "<div class=\"note\">\n*not parsed*\n</div>\n<p>Some <b title='x'>bold</b> &amp; &lt;3</p>\n<!-- comment -->\n"
+        );
+    }
+
+    #[test]
+    fn test_output_style_options() {
+        let options = ParserOptions::new()
+            .soft_break(SoftBreakStyle::Space)
+            .list_indent(0);
+        let result = MarkdownParser::with_options("one\ntwo\n\n- item", options).parse();
+        assert_eq!(result, "<p>one two</p>\n<ul>\n<li>item</li>\n</ul>\n");
+
+        let options = ParserOptions::new()
+            .soft_break(SoftBreakStyle::HardBreak)
+            .xhtml(true);
+        let result = MarkdownParser::with_options("one\ntwo", options).parse();
+        assert_eq!(result, "<p>one<br />\ntwo</p>\n");
+    }
+
+    #[test]
+    fn test_soft_breaks_as_hard_breaks_stop_at_block_boundaries() {
+        let options = ParserOptions::new().soft_break(SoftBreakStyle::HardBreak);
+        let parse = |input| MarkdownParser::with_options(input, options.clone()).parse();
+
+        // The line ending a block is no break, whatever block follows
+        assert_eq!(
+            parse("one\ntwo\n# h\n- a\n  b  \n> q\nr\n\nend\\"),
+            "<p>one<br>\ntwo</p>\n<h1>h</h1>\n<ul>\n  <li>a<br>\nb</li>\n</ul>\n\
+             <blockquote>\n<p>q<br>\nr</p>\n</blockquote>\n<p>end\\</p>\n"
+        );
+
+        // Line endings in code spans are spaces, not breaks
+        assert_eq!(parse("`x\ny`\nz"), "<p><code>x y</code><br>\nz</p>\n");
+    }
+
+    #[test]
+    fn test_hard_breaks() {
+        let result =
+            MarkdownParser::new("two  \nspaces\\\nbackslash \t\r\n  soft\\\\\nescaped  ").parse();
+        assert_eq!(
+            result,
+            "<p>two<br>\nspaces<br>\nbackslash\nsoft\\\nescaped</p>\n"
+        );
+
+        // Not at the end of a block, nor inside code spans
+        assert_eq!(MarkdownParser::new("end\\").parse(), "<p>end\\</p>\n");
+        assert_eq!(
+            MarkdownParser::new("`a  \nb`").parse(),
+            "<p><code>a   b</code></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_void_tag_style() {
+        assert_eq!(HtmlRenderer::new().void_tag_end(), ">");
+        let options = ParserOptions::new().xhtml(true);
+        assert_eq!(HtmlRenderer::with_options(&options).void_tag_end(), " />");
+    }
+
+    #[test]
+    fn test_setext_headings() {
+        let result =
+            MarkdownParser::new("Title *one*\n=====\n\nSub\ntitle  \n  ---\n\nnot\n= heading")
+                .parse();
+        assert_eq!(
+            result,
+            "<h1>Title <em>one</em></h1>\n<h2>Sub\ntitle</h2>\n<p>not\n= heading</p>\n"
+        );
+
+        // A lazy continuation line can't underline the quoted paragraph
+        let result = MarkdownParser::new("> quoted\n===\n\n> heading\n> ===").parse();
+        assert_eq!(
+            result,
+            "<blockquote>\n<p>quoted\n===</p>\n</blockquote>\n<blockquote>\n<h1>heading</h1>\n</blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn test_thematic_breaks() {
+        let result = MarkdownParser::new("***\n- a\n- - -\n- b\n\npara\n_ _ _\n").parse();
+        assert_eq!(
+            result,
+            "<hr>\n<ul>\n  <li>a</li>\n</ul>\n<hr>\n<ul>\n  <li>b</li>\n</ul>\n<p>para</p>\n<hr>\n"
+        );
+
+        let options = ParserOptions::new().xhtml(true);
+        let result = MarkdownParser::with_options("a\n\n---", options).parse();
+        assert_eq!(result, "<p>a</p>\n<hr />\n");
+    }
+
+    #[test]
+    fn test_list_items_interrupting_paragraphs() {
+        // Only a non-empty item, numbered 1 if ordered, starts a list there
+        let result = MarkdownParser::new("a\n2. b\n*\n1234567890. c\n1) d\n* e").parse();
+        assert_eq!(
+            result,
+            "<p>a\n2. b\n*\n1234567890. c</p>\n<ol>\n  <li>d</li>\n</ol>\n<ul>\n  <li>e</li>\n</ul>\n"
+        );
+
+        // After a blank line, any item starts a list
+        let result = MarkdownParser::new("a\n\n2. b").parse();
+        assert_eq!(result, "<p>a</p>\n<ol start=\"2\">\n  <li>b</li>\n</ol>\n");
+    }
+
+    #[test]
+    fn test_images() {
+        let result =
+            MarkdownParser::new("![a *fancy* `cat`](/cat.png \"My cat\") and ![](x.gif)").parse();
+        assert_eq!(
+            result,
+            "<p><img src=\"/cat.png\" alt=\"a fancy cat\" title=\"My cat\"> and <img src=\"x.gif\" alt=\"\"></p>\n"
+        );
+
+        let result = MarkdownParser::new("# ![logo](l.svg 'Logo') Home").parse();
+        assert_eq!(
+            result,
+            "<h1><img src=\"l.svg\" alt=\"logo\" title=\"Logo\"> Home</h1>\n"
+        );
+    }
+
+    #[test]
+    fn test_image_events() {
+        let events: Vec<Event> = MarkdownParser::new("![*a*](b \"c\")").events().collect();
+        let tag = Tag::Image {
+            dest_url: "b".into(),
+            title: "c".into(),
+        };
+        assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Start(tag.clone()),
+                Event::Start(Tag::Emphasis),
+                Event::Text("a".into()),
+                Event::End(Tag::Emphasis),
+                Event::End(tag),
+                Event::End(Tag::Paragraph),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_image_options() {
+        let options = ParserOptions::new()
+            .lazy_images(true)
+            .image_src_prefix("https://cdn.example.com/")
+            .xhtml(true);
+        let input = "![a](/img/a.png) ![b](b.png) ![c](https://example.com/c.png)";
+        let result = MarkdownParser::with_options(input, options).parse();
+        assert_eq!(
+            result,
+            "<p><img src=\"https://cdn.example.com/img/a.png\" alt=\"a\" loading=\"lazy\" /> \
+             <img src=\"https://cdn.example.com/b.png\" alt=\"b\" loading=\"lazy\" /> \
+             <img src=\"https://example.com/c.png\" alt=\"c\" loading=\"lazy\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let root = std::env::temp_dir().join(format!("md2html-images-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("img")).unwrap();
+        std::fs::write(root.join("img/dot.gif"), b"GIF89a\x20\x03\x58\x02\0\0").unwrap();
+
+        let options = ParserOptions::new().image_dimensions(&root);
+        let input = "![dot](/img/dot.gif?v=2) ![missing](img/none.png)";
+        let result = MarkdownParser::with_options(input, options).parse();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            result,
+            "<p><img src=\"/img/dot.gif?v=2\" alt=\"dot\" width=\"800\" height=\"600\"> \
+             <img src=\"img/none.png\" alt=\"missing\"></p>\n"
+        );
+    }
+
     #[test]
     fn test_code_block() {
         let parser = MarkdownParser::new("```\ncode block\nwith multiple lines\n```");
@@ -423,6 +2721,282 @@ mod tests {
     fn test_paragraph() {
         let parser = MarkdownParser::new("This is a paragraph\nwith multiple lines.");
         let result = parser.parse();
-        assert!(result.contains("<p>This is a paragraph with multiple lines.</p>"));
+        assert!(result.contains("<p>This is a paragraph\nwith multiple lines.</p>"));
+    }
+
+    #[test]
+    fn test_events() {
+        let parser = MarkdownParser::new("# Title\n\nSome *text*\nwith [a link](/x).");
+        let events: Vec<Event> = parser.events().collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::Heading(1)),
+                Event::Text("Title".into()),
+                Event::End(Tag::Heading(1)),
+                Event::Start(Tag::Paragraph),
+                Event::Text("Some ".into()),
+                Event::Start(Tag::Emphasis),
+                Event::Text("text".into()),
+                Event::End(Tag::Emphasis),
+                Event::SoftBreak,
+                Event::Text("with ".into()),
+                Event::Start(Tag::Link {
+                    dest_url: "/x".into(),
+                    title: "".into()
+                }),
+                Event::Text("a link".into()),
+                Event::End(Tag::Link {
+                    dest_url: "/x".into(),
+                    title: "".into()
+                }),
+                Event::Text(".".into()),
+                Event::End(Tag::Paragraph),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tight_list_events_omit_paragraphs() {
+        let events: Vec<Event> = MarkdownParser::new("- a\n- b").events().collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::List(None)),
+                Event::Start(Tag::Item(None)),
+                Event::Text("a".into()),
+                Event::End(Tag::Item(None)),
+                Event::Start(Tag::Item(None)),
+                Event::Text("b".into()),
+                Event::End(Tag::Item(None)),
+                Event::End(Tag::List(None)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_push_html_renders_transformed_events() {
+        let parser = MarkdownParser::new("See [docs](/docs) and [home](/).");
+        let events = parser.events().map(|event| match event {
+            Event::Start(Tag::Link { dest_url, title }) => Event::Start(Tag::Link {
+                dest_url: format!("https://example.com{dest_url}").into(),
+                title,
+            }),
+            event => event,
+        });
+        let mut html = String::new();
+        push_html(&mut html, events);
+        assert_eq!(
+            html,
+            "<p>See <a href=\"https://example.com/docs\">docs</a> and <a href=\"https://example.com/\">home</a>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_code_block_language_class() {
+        let result = MarkdownParser::new("```haskell {linenos}\nmain = pure ()\n```").parse();
+        assert_eq!(
+            result,
+            "<pre><code class=\"language-haskell\">main = pure ()\n</code></pre>\n"
+        );
+    }
+
+    #[cfg(feature = "highlight")]
+    #[test]
+    fn test_code_block_highlighting() {
+        let result = MarkdownParser::new("```rust\nlet s = \"<b>\";\n```").parse();
+        assert_eq!(
+            result,
+            "<pre><code class=\"language-rust\"><span class=\"tok-keyword\">let</span> s = \
+             <span class=\"tok-string\">&quot;&lt;b&gt;&quot;</span>;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_code_fence_length_and_tildes() {
+        let result = MarkdownParser::new("````\n```\nstill code\n````\n\n~~~\n```\n~~~~\n").parse();
+        assert_eq!(
+            result,
+            "<pre><code>```\nstill code\n</code></pre>\n<pre><code>```\n</code></pre>\n"
+        );
+
+        // Content loses as much indentation as the opening fence had
+        let result = MarkdownParser::new("  ~~~ ini\n  a = 1\n    b = 2\n c\n  ~~~").parse();
+        assert_eq!(
+            result,
+            "<pre><code class=\"language-ini\">a = 1\n  b = 2\nc\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_indented_code_block() {
+        let input = "Text\n\n    fn main() {\n        <body>\n\n\t}\n\n\nafter";
+        assert_eq!(
+            MarkdownParser::new(input).parse(),
+            "<p>Text</p>\n<pre><code>fn main() {\n    &lt;body&gt;\n\n}\n</code></pre>\n<p>after</p>\n"
+        );
+
+        // Indented lines can't interrupt a paragraph
+        assert_eq!(
+            MarkdownParser::new("para\n    continued\n> quote\n    lazy").parse(),
+            "<p>para\ncontinued</p>\n<blockquote>\n<p>quote\nlazy</p>\n</blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn test_code_block_info_string_in_events() {
+        let parser = MarkdownParser::new("```rust {linenos}\nx\n```");
+        let events: Vec<Event> = parser.events().collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Start(Tag::CodeBlock(Cow::Borrowed("rust {linenos}"))),
+                Event::Text(Cow::Borrowed("x\n")),
+                Event::End(Tag::CodeBlock(Cow::Borrowed("rust {linenos}"))),
+            ]
+        );
+
+        #[derive(Default)]
+        struct LineNumbers {
+            html: HtmlRenderer,
+        }
+
+        impl Renderer for LineNumbers {
+            fn html_renderer(&mut self) -> &mut HtmlRenderer {
+                &mut self.html
+            }
+
+            fn code_block(&mut self, out: &mut String, info: &str, code: &str) {
+                let numbered = info.contains("{linenos}");
+                out.push_str(&format!("<pre data-linenos=\"{numbered}\">{code}</pre>\n"));
+            }
+        }
+
+        let result = parser.parse_with_renderer(&mut LineNumbers::default());
+        assert_eq!(result, "<pre data-linenos=\"true\">x\n</pre>\n");
+    }
+
+    #[test]
+    fn test_custom_renderer_overrides_headings() {
+        use std::fmt::Write;
+
+        #[derive(Default)]
+        struct Anchors {
+            html: HtmlRenderer,
+        }
+
+        impl Renderer for Anchors {
+            fn html_renderer(&mut self) -> &mut HtmlRenderer {
+                &mut self.html
+            }
+
+            fn heading(&mut self, out: &mut String, level: usize, text: &str, entering: bool) {
+                if entering {
+                    write!(out, "<h{level} id=\"").unwrap();
+                    escape_html_into(&text.to_lowercase().replace(' ', "-"), out);
+                    out.push_str("\">");
+                } else {
+                    out.push_str(&format!("</h{level}>\n"));
+                }
+            }
+        }
+
+        let parser = MarkdownParser::new("## Getting `Started`\n\n- **item**\n\n# \"><b>");
+        let result = parser.parse_with_renderer(&mut Anchors::default());
+        assert_eq!(
+            result,
+            "<h2 id=\"getting-started\">Getting <code>Started</code></h2>\n<ul>\n  <li><strong>item</strong></li>\n</ul>\n\
+             <h1 id=\"&quot;&gt;&lt;b&gt;\">&quot;&gt;&lt;b&gt;</h1>\n"
+        );
+    }
+
+    #[test]
+    fn test_streaming_output_matches_parse() {
+        let input = "# Title\n\n- a\n  - b\n\n> quote\n\n| x |\n|---|\n| y |\n\n```\ncode\n```";
+        let parser = MarkdownParser::new(input);
+        let expected = parser.parse();
+
+        let mut fmt_output = String::new();
+        parser.parse_into(&mut fmt_output).unwrap();
+        assert_eq!(fmt_output, expected);
+
+        let mut io_output = Vec::new();
+        parser.render_to_writer(&mut io_output).unwrap();
+        assert_eq!(String::from_utf8(io_output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_render_to_writer_writes_block_by_block() {
+        struct Blocks(Vec<String>);
+
+        impl io::Write for Blocks {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.push(String::from_utf8(buf.to_vec()).unwrap());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut blocks = Blocks(Vec::new());
+        MarkdownParser::new("# One\n\ntwo\n\n- three\n- four")
+            .render_to_writer(&mut blocks)
+            .unwrap();
+        assert_eq!(
+            blocks.0,
+            vec![
+                "<h1>One</h1>\n",
+                "<p>two</p>\n",
+                "<ul>\n  <li>three</li>\n  <li>four</li>\n</ul>\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blockquote() {
+        let parser = MarkdownParser::new("> # Title\n> Quoted **text**\n>\n> - item");
+        let result = parser.parse();
+        assert_eq!(
+            result,
+            "<blockquote>\n<h1>Title</h1>\n<p>Quoted <strong>text</strong></p>\n<ul>\n  <li>item</li>\n</ul>\n</blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_blockquote_with_lazy_continuation() {
+        let parser = MarkdownParser::new(">> nested\nlazy line\n\nafter");
+        let result = parser.parse();
+        assert_eq!(
+            result,
+            "<blockquote>\n<blockquote>\n<p>nested\nlazy line</p>\n</blockquote>\n</blockquote>\n<p>after</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_blockquote_code_fence_is_not_lazy() {
+        let parser = MarkdownParser::new("> ```\n> code\nnot code");
+        let result = parser.parse();
+        assert!(result.contains("<blockquote>\n<pre><code>code\n</code></pre>\n</blockquote>"));
+        assert!(result.contains("<p>not code</p>"));
+
+        // Once the fence closes, the quote's paragraph takes lazy lines again
+        let parser = MarkdownParser::new("> ```\n> code\n> ```\n> text\nlazy");
+        assert!(parser
+            .parse()
+            .ends_with("<p>text\nlazy</p>\n</blockquote>\n"));
+    }
+
+    #[test]
+    fn test_long_lazy_continuation() {
+        // Each lazy line is checked without rescanning the lines before it
+        let lines = "lazy\n".repeat(20_000);
+        let result = MarkdownParser::new(&format!("> a\n{lines}")).parse();
+        assert!(result.starts_with("<blockquote>\n<p>a\nlazy\n"));
+        assert!(result.ends_with("lazy</p>\n</blockquote>\n"));
+
+        let result = MarkdownParser::new(&format!("- a\n{lines}")).parse();
+        assert!(result.ends_with("lazy</li>\n</ul>\n"));
     }
 }
@@ -1,47 +1,154 @@
 use std::env;
-use std::fs;
-use std::io::{self, Read};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read};
 use std::process;
 
-use md2html::MarkdownParser;
+use md2html::{MarkdownParser, ParserOptions, ReaderParser, SoftBreakStyle};
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {program} [options] [input_file] [output_file]");
+    eprintln!("  If no files specified, reads from stdin and writes to stdout");
+    eprintln!("  If only input_file specified, writes to stdout");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --no-tables                Disable GFM pipe tables");
+    eprintln!("  --no-task-lists            Disable GFM task list items");
+    eprintln!("  --raw-html                 Pass raw HTML through instead of escaping it");
+    eprintln!("  --extended-autolinks       Link bare URLs, www. addresses and emails");
+    eprintln!("  --strikethrough            Render ~~text~~ as <del>");
+    eprintln!("  --mark                     Render ==text== as <mark>");
+    eprintln!("  --superscript              Render ^text^ as <sup>");
+    eprintln!("  --subscript                Render ~text~ as <sub>");
+    eprintln!("  --footnotes                Number [^label] footnotes and list them at the end");
+    eprintln!("  --soft-break <space|newline|br>");
+    eprintln!("                             How line breaks inside paragraphs are written");
+    eprintln!("  --list-indent <n>          Indent <li> tags by n spaces (default 2)");
+    eprintln!("  --xhtml                    Write void elements as <br /> instead of <br>");
+    eprintln!("  --table-align-class <prefix>");
+    eprintln!("                             Use class=\"<prefix>left\" etc. for table alignment");
+    eprintln!("  --lazy-images              Add loading=\"lazy\" to images");
+    eprintln!("  --image-src-prefix <prefix>");
+    eprintln!("                             Prepend prefix (e.g. a CDN URL) to local image paths");
+    eprintln!("  --image-dimensions <dir>   Add width/height of local images found under dir");
+    eprintln!("  --whole-input              Read all of stdin before converting it, so reference");
+    eprintln!("                             links resolve to definitions further down. Without");
+    eprintln!("                             it stdin is converted block by block as lines arrive");
+}
+
+fn usage_error(program: &str, message: &str) -> ! {
+    eprintln!("Error: {message}");
+    print_usage(program);
+    process::exit(1);
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let program = &args[0];
 
-    if args.len() > 3 {
-        eprintln!("Usage: {} [input_file] [output_file]", args[0]);
-        eprintln!("  If no files specified, reads from stdin and writes to stdout");
-        eprintln!("  If only input_file specified, writes to stdout");
-        process::exit(1);
+    let mut options = ParserOptions::new();
+    // Piped input may be unbounded, so it is converted block by block as
+    // lines arrive unless the whole document is asked for. Footnotes are
+    // listed at the end, so they need the whole document too.
+    let mut whole_input = false;
+    let mut files: Vec<&String> = Vec::new();
+    let mut rest = args[1..].iter();
+
+    while let Some(arg) = rest.next() {
+        let mut value = |flag: &str| match rest.next() {
+            Some(value) => value.clone(),
+            None => usage_error(program, &format!("{flag} requires a value")),
+        };
+
+        options = match arg.as_str() {
+            "-h" | "--help" => {
+                print_usage(program);
+                return;
+            }
+            "--no-tables" => options.tables(false),
+            "--no-task-lists" => options.task_lists(false),
+            "--raw-html" => options.raw_html(true),
+            "--extended-autolinks" => options.extended_autolinks(true),
+            "--strikethrough" => options.strikethrough(true),
+            "--mark" => options.mark(true),
+            "--superscript" => options.superscript(true),
+            "--subscript" => options.subscript(true),
+            "--footnotes" => {
+                whole_input = true;
+                options.footnotes(true)
+            }
+            "--whole-input" => {
+                whole_input = true;
+                options
+            }
+            "--xhtml" => options.xhtml(true),
+            "--soft-break" => match value("--soft-break").as_str() {
+                "space" => options.soft_break(SoftBreakStyle::Space),
+                "newline" => options.soft_break(SoftBreakStyle::Newline),
+                "br" => options.soft_break(SoftBreakStyle::HardBreak),
+                other => usage_error(program, &format!("unknown soft break style '{other}'")),
+            },
+            "--list-indent" => match value("--list-indent").parse() {
+                Ok(spaces) => options.list_indent(spaces),
+                Err(_) => usage_error(program, "--list-indent expects a number"),
+            },
+            "--table-align-class" => options.table_alignment_class(value("--table-align-class")),
+            "--lazy-images" => options.lazy_images(true),
+            "--image-src-prefix" => options.image_src_prefix(value("--image-src-prefix")),
+            "--image-dimensions" => options.image_dimensions(value("--image-dimensions")),
+            flag if flag.starts_with("--") => {
+                usage_error(program, &format!("unknown option '{flag}'"))
+            }
+            _ => {
+                files.push(arg);
+                options
+            }
+        };
     }
 
-    let input = if args.len() > 1 {
-        match fs::read_to_string(&args[1]) {
-            Ok(content) => content,
-            Err(e) => {
-                eprintln!("Error reading input file '{}': {}", args[1], e);
+    if files.len() > 2 {
+        print_usage(program);
+        process::exit(1);
+    }
+    let input = match files.first() {
+        None if !whole_input => {
+            if let Err(e) = ReaderParser::with_options(io::stdin().lock(), options)
+                .render_to_writer(BufWriter::new(io::stdout().lock()))
+            {
+                eprintln!("Error converting stdin: {e}");
                 process::exit(1);
             }
+            return;
         }
-    } else {
-        let mut buffer = String::new();
-        if let Err(e) = io::stdin().read_to_string(&mut buffer) {
-            eprintln!("Error reading from stdin: {e}");
-            process::exit(1);
+        None => {
+            let mut content = String::new();
+            if let Err(e) = io::stdin().lock().read_to_string(&mut content) {
+                eprintln!("Error reading stdin: {e}");
+                process::exit(1);
+            }
+            content
         }
-        buffer
+        Some(input_file) => match fs::read_to_string(input_file) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Error reading input file '{input_file}': {e}");
+                process::exit(1);
+            }
+        },
     };
 
-    let parser = MarkdownParser::new(&input);
-    let html_output = parser.parse();
+    let parser = MarkdownParser::with_options(&input, options);
 
-    if args.len() > 2 {
-        if let Err(e) = fs::write(&args[2], html_output) {
-            eprintln!("Error writing to output file '{}': {}", args[2], e);
+    // HTML is streamed out block by block rather than built up in memory
+    if let [input_file, output_file] = files[..] {
+        let result = File::create(output_file)
+            .and_then(|file| parser.render_to_writer(BufWriter::new(file)));
+        if let Err(e) = result {
+            eprintln!("Error writing to output file '{output_file}': {e}");
             process::exit(1);
         }
-        println!("Successfully converted '{}' to '{}'", args[1], args[2]);
-    } else {
-        print!("{html_output}");
+        println!("Successfully converted '{input_file}' to '{output_file}'");
+    } else if let Err(e) = parser.render_to_writer(BufWriter::new(io::stdout().lock())) {
+        eprintln!("Error writing to stdout: {e}");
+        process::exit(1);
     }
 }
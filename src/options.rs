@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+
+/// Configuration for [`MarkdownParser::with_options`], built up with chained
+/// setters:
+///
+/// ```
+/// use md2html::{MarkdownParser, ParserOptions, SoftBreakStyle};
+///
+/// let options = ParserOptions::new()
+///     .raw_html(true)
+///     .soft_break(SoftBreakStyle::Space);
+/// let html = MarkdownParser::with_options("<b>hi</b>\nthere", options).parse();
+/// assert_eq!(html, "<p><b>hi</b> there</p>\n");
+/// ```
+///
+/// [`MarkdownParser::with_options`]: crate::MarkdownParser::with_options
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+    pub(crate) tables: bool,
+    pub(crate) task_lists: bool,
+    pub(crate) raw_html: bool,
+    pub(crate) extended_autolinks: bool,
+    pub(crate) strikethrough: bool,
+    pub(crate) mark: bool,
+    pub(crate) superscript: bool,
+    pub(crate) subscript: bool,
+    pub(crate) footnotes: bool,
+    pub(crate) soft_break: SoftBreakStyle,
+    pub(crate) list_indent: usize,
+    pub(crate) xhtml: bool,
+    pub(crate) table_alignment_class: Option<String>,
+    pub(crate) lazy_images: bool,
+    pub(crate) image_src_prefix: Option<String>,
+    pub(crate) image_size_root: Option<PathBuf>,
+}
+
+/// How a line ending inside a paragraph is written to the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftBreakStyle {
+    /// Join the lines with a single space
+    Space,
+    /// Keep the line ending as a newline
+    Newline,
+    /// Write every line ending as a `<br>`, like a hard break, e.g. for chat
+    /// messages where each line was meant to stand on its own
+    HardBreak,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            task_lists: true,
+            raw_html: false,
+            extended_autolinks: false,
+            strikethrough: false,
+            mark: false,
+            superscript: false,
+            subscript: false,
+            footnotes: false,
+            soft_break: SoftBreakStyle::Newline,
+            list_indent: 2,
+            xhtml: false,
+            table_alignment_class: None,
+            lazy_images: false,
+            image_src_prefix: None,
+            image_size_root: None,
+        }
+    }
+}
+
+impl ParserOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables GFM pipe tables. On by default.
+    pub fn tables(mut self, enabled: bool) -> Self {
+        self.tables = enabled;
+        self
+    }
+
+    /// Renders list items starting with `[ ]` or `[x]` as task list items
+    /// with a disabled checkbox. On by default.
+    pub fn task_lists(mut self, enabled: bool) -> Self {
+        self.task_lists = enabled;
+        self
+    }
+
+    /// Passes HTML blocks and inline tags through unescaped. Off by default,
+    /// so untrusted input cannot inject markup.
+    pub fn raw_html(mut self, enabled: bool) -> Self {
+        self.raw_html = enabled;
+        self
+    }
+
+    /// Links bare URLs starting with `http://`, `https://` or `www.`, and
+    /// email addresses, as GitHub does. Off by default; `<https://...>`
+    /// autolinks are always recognized.
+    pub fn extended_autolinks(mut self, enabled: bool) -> Self {
+        self.extended_autolinks = enabled;
+        self
+    }
+
+    /// Renders `~~text~~` as `<del>`. Off by default.
+    pub fn strikethrough(mut self, enabled: bool) -> Self {
+        self.strikethrough = enabled;
+        self
+    }
+
+    /// Renders `==text==` as `<mark>`. Off by default.
+    pub fn mark(mut self, enabled: bool) -> Self {
+        self.mark = enabled;
+        self
+    }
+
+    /// Renders `^text^` as `<sup>`, e.g. `x^2^`. Off by default.
+    pub fn superscript(mut self, enabled: bool) -> Self {
+        self.superscript = enabled;
+        self
+    }
+
+    /// Renders `~text~` as `<sub>`, e.g. `H~2~O`. Off by default.
+    pub fn subscript(mut self, enabled: bool) -> Self {
+        self.subscript = enabled;
+        self
+    }
+
+    /// Renders `[^label]` references to `[^label]: text` definitions as
+    /// numbered footnotes, listed at the end of the document. Definitions no
+    /// reference points to are dropped. Off by default, since CommonMark
+    /// reads such definitions as link reference definitions.
+    pub fn footnotes(mut self, enabled: bool) -> Self {
+        self.footnotes = enabled;
+        self
+    }
+
+    /// Sets how soft line breaks are rendered. Defaults to
+    /// [`SoftBreakStyle::Newline`].
+    pub fn soft_break(mut self, style: SoftBreakStyle) -> Self {
+        self.soft_break = style;
+        self
+    }
+
+    /// Sets how many spaces `<li>` tags are indented by. Defaults to 2.
+    pub fn list_indent(mut self, spaces: usize) -> Self {
+        self.list_indent = spaces;
+        self
+    }
+
+    /// Writes void elements XHTML-style, e.g. `<br />` instead of `<br>`.
+    pub fn xhtml(mut self, enabled: bool) -> Self {
+        self.xhtml = enabled;
+        self
+    }
+
+    /// Renders aligned table cells with `class="{prefix}left"` (or `center`,
+    /// `right`) instead of an inline `style="text-align: ..."` attribute.
+    pub fn table_alignment_class(mut self, prefix: impl Into<String>) -> Self {
+        self.table_alignment_class = Some(prefix.into());
+        self
+    }
+
+    /// Adds `loading="lazy"` to every `<img>`, deferring offscreen images.
+    pub fn lazy_images(mut self, enabled: bool) -> Self {
+        self.lazy_images = enabled;
+        self
+    }
+
+    /// Prepends `prefix` to the `src` of images given by a relative or
+    /// root-relative path, e.g. to serve them from a CDN. Absolute URLs are
+    /// left alone.
+    pub fn image_src_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.image_src_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Looks up images given by a local path under `root` and writes their
+    /// `width` and `height`, read from the PNG, GIF or JPEG header, so the page
+    /// does not shift as they load. Images that cannot be read, or whose path
+    /// leads outside `root`, get no size.
+    pub fn image_dimensions(mut self, root: impl Into<PathBuf>) -> Self {
+        self.image_size_root = Some(root.into());
+        self
+    }
+}
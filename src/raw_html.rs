@@ -0,0 +1,394 @@
+//! Recognition of raw HTML, passed through when `ParserOptions::raw_html` is
+//! enabled. Follows the CommonMark rules for HTML blocks and inline tags.
+
+/// Tags that start an HTML block running to the next blank line.
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+/// Tags whose content may contain blank lines, so their block only ends at
+/// the matching closing tag.
+const RAW_TEXT_TAGS: &[&str] = &["pre", "script", "style", "textarea"];
+
+/// The seven kinds of HTML block, which differ in how they end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtmlBlockKind {
+    /// `<pre>`, `<script>`, `<style>` or `<textarea>`
+    RawText,
+    /// `<!-- ... -->`
+    Comment,
+    /// `<? ... ?>`
+    ProcessingInstruction,
+    /// `<!DOCTYPE ...>` and other declarations
+    Declaration,
+    /// `<![CDATA[ ... ]]>`
+    CData,
+    /// A known block-level tag
+    BlockTag,
+    /// Any other complete tag alone on its line
+    OtherTag,
+}
+
+impl HtmlBlockKind {
+    /// Only blocks of the first six kinds may interrupt a paragraph.
+    pub fn can_interrupt_paragraph(self) -> bool {
+        self != HtmlBlockKind::OtherTag
+    }
+
+    /// Returns true if `line` closes a block of this kind. Blocks ending at a
+    /// blank line are closed by the caller instead.
+    pub fn ends_on(self, line: &str) -> bool {
+        match self {
+            HtmlBlockKind::RawText => {
+                let lower = line.to_ascii_lowercase();
+                RAW_TEXT_TAGS
+                    .iter()
+                    .any(|tag| lower.contains(&format!("</{tag}>")))
+            }
+            HtmlBlockKind::Comment => line.contains("-->"),
+            HtmlBlockKind::ProcessingInstruction => line.contains("?>"),
+            HtmlBlockKind::Declaration => line.contains('>'),
+            HtmlBlockKind::CData => line.contains("]]>"),
+            HtmlBlockKind::BlockTag | HtmlBlockKind::OtherTag => false,
+        }
+    }
+}
+
+/// Returns the kind of HTML block `line` opens, if any.
+pub fn html_block_start(line: &str) -> Option<HtmlBlockKind> {
+    let indent = line.bytes().take_while(|&b| b == b' ').count();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let bytes = rest.as_bytes();
+    if bytes.first() != Some(&b'<') {
+        return None;
+    }
+
+    if rest.starts_with("<!--") {
+        return Some(HtmlBlockKind::Comment);
+    }
+    if rest.starts_with("<?") {
+        return Some(HtmlBlockKind::ProcessingInstruction);
+    }
+    if rest.starts_with("<![CDATA[") {
+        return Some(HtmlBlockKind::CData);
+    }
+    if rest.starts_with("<!") && bytes.get(2).is_some_and(u8::is_ascii_alphabetic) {
+        return Some(HtmlBlockKind::Declaration);
+    }
+
+    let closing = bytes.get(1) == Some(&b'/');
+    let name_start = if closing { 2 } else { 1 };
+    let name_len = bytes[name_start..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'-')
+        .count();
+    if name_len == 0 {
+        return None;
+    }
+    let name = rest[name_start..name_start + name_len].to_ascii_lowercase();
+    let after = &bytes[name_start + name_len..];
+    let name_ends = matches!(after, [] | [b' ' | b'\t' | b'>', ..]) || after.starts_with(b"/>");
+
+    if !closing && name_ends && RAW_TEXT_TAGS.contains(&name.as_str()) {
+        return Some(HtmlBlockKind::RawText);
+    }
+    if name_ends && BLOCK_TAGS.contains(&name.as_str()) {
+        return Some(HtmlBlockKind::BlockTag);
+    }
+
+    // Any other tag must be complete and alone on its line
+    let tag_len = scan_open_tag(bytes, 0, &mut UnterminatedHtml::default())
+        .or_else(|| scan_closing_tag(bytes, 0))?;
+    if rest[tag_len..].trim().is_empty() && !RAW_TEXT_TAGS.contains(&name.as_str()) {
+        Some(HtmlBlockKind::OtherTag)
+    } else {
+        None
+    }
+}
+
+/// Where the search for the terminator of each kind of inline HTML construct
+/// last failed in a text. The terminator does not occur past that offset, so
+/// later constructs of the same kind fail without searching the text again.
+#[derive(Default)]
+pub struct UnterminatedHtml {
+    comment: Option<usize>,
+    processing_instruction: Option<usize>,
+    declaration: Option<usize>,
+    cdata: Option<usize>,
+    double_quote: Option<usize>,
+    single_quote: Option<usize>,
+}
+
+/// Scans an inline HTML construct (tag, comment, processing instruction,
+/// declaration or CDATA section) starting at the `<` at `start`, returning
+/// its length. `unterminated` must only ever be used with the same `bytes`.
+pub fn scan_inline_html(
+    bytes: &[u8],
+    start: usize,
+    unterminated: &mut UnterminatedHtml,
+) -> Option<usize> {
+    match bytes.get(start + 1)? {
+        b'/' => scan_closing_tag(bytes, start),
+        b'?' => scan_until(
+            bytes,
+            start + 2,
+            b"?>",
+            &mut unterminated.processing_instruction,
+        )
+        .map(|end| end - start),
+        b'!' => {
+            let rest = &bytes[start..];
+            if rest.starts_with(b"<!-->") {
+                Some(5)
+            } else if rest.starts_with(b"<!--->") {
+                Some(6)
+            } else if rest.starts_with(b"<!--") {
+                scan_until(bytes, start + 4, b"-->", &mut unterminated.comment)
+                    .map(|end| end - start)
+            } else if rest.starts_with(b"<![CDATA[") {
+                scan_until(bytes, start + 9, b"]]>", &mut unterminated.cdata).map(|end| end - start)
+            } else if rest.get(2).is_some_and(u8::is_ascii_alphabetic) {
+                scan_until(bytes, start + 3, b">", &mut unterminated.declaration)
+                    .map(|end| end - start)
+            } else {
+                None
+            }
+        }
+        _ => scan_open_tag(bytes, start, unterminated),
+    }
+}
+
+/// Returns the position just past the first `terminator` at or after `from`.
+/// `missing_from` is where an earlier search for it failed, and is updated
+/// when this one fails.
+fn scan_until(
+    bytes: &[u8],
+    from: usize,
+    terminator: &[u8],
+    missing_from: &mut Option<usize>,
+) -> Option<usize> {
+    if missing_from.is_some_and(|missing| from >= missing) {
+        return None;
+    }
+    let end = bytes
+        .get(from..)?
+        .windows(terminator.len())
+        .position(|window| window == terminator)
+        .map(|pos| from + pos + terminator.len());
+    if end.is_none() {
+        *missing_from = Some(from);
+    }
+    end
+}
+
+fn scan_tag_name(bytes: &[u8], start: usize) -> Option<usize> {
+    if !bytes.get(start)?.is_ascii_alphabetic() {
+        return None;
+    }
+    let len = bytes[start..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'-')
+        .count();
+    Some(start + len)
+}
+
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && matches!(bytes[i], b' ' | b'\t' | b'\n' | b'\r') {
+        i += 1;
+    }
+    i
+}
+
+fn scan_open_tag(bytes: &[u8], start: usize, unterminated: &mut UnterminatedHtml) -> Option<usize> {
+    let mut i = scan_tag_name(bytes, start + 1)?;
+
+    loop {
+        let after_space = skip_whitespace(bytes, i);
+        match bytes.get(after_space)? {
+            b'>' => return Some(after_space + 1 - start),
+            b'/' if bytes.get(after_space + 1) == Some(&b'>') => {
+                return Some(after_space + 2 - start)
+            }
+            // Attributes must be separated from what precedes them
+            _ if after_space == i => return None,
+            _ => i = scan_attribute(bytes, after_space, unterminated)?,
+        }
+    }
+}
+
+fn scan_attribute(
+    bytes: &[u8],
+    start: usize,
+    unterminated: &mut UnterminatedHtml,
+) -> Option<usize> {
+    let first = *bytes.get(start)?;
+    if !(first.is_ascii_alphabetic() || first == b'_' || first == b':') {
+        return None;
+    }
+    let mut i = start + 1;
+    while i < bytes.len()
+        && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'_' | b'.' | b':' | b'-'))
+    {
+        i += 1;
+    }
+
+    let before_equals = skip_whitespace(bytes, i);
+    if bytes.get(before_equals) != Some(&b'=') {
+        return Some(i);
+    }
+    let value_start = skip_whitespace(bytes, before_equals + 1);
+    match *bytes.get(value_start)? {
+        b'"' => scan_until(
+            bytes,
+            value_start + 1,
+            b"\"",
+            &mut unterminated.double_quote,
+        ),
+        b'\'' => scan_until(bytes, value_start + 1, b"'", &mut unterminated.single_quote),
+        _ => {
+            let len = bytes[value_start..]
+                .iter()
+                .take_while(|b| {
+                    !matches!(
+                        b,
+                        b' ' | b'\t' | b'\n' | b'\r' | b'"' | b'\'' | b'=' | b'<' | b'>' | b'`'
+                    )
+                })
+                .count();
+            (len > 0).then_some(value_start + len)
+        }
+    }
+}
+
+fn scan_closing_tag(bytes: &[u8], start: usize) -> Option<usize> {
+    if bytes.get(start + 1) != Some(&b'/') {
+        return None;
+    }
+    let i = skip_whitespace(bytes, scan_tag_name(bytes, start + 2)?);
+    (bytes.get(i) == Some(&b'>')).then_some(i + 1 - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_block_start() {
+        assert_eq!(html_block_start("<div>"), Some(HtmlBlockKind::BlockTag));
+        assert_eq!(html_block_start("</DIV>"), Some(HtmlBlockKind::BlockTag));
+        assert_eq!(
+            html_block_start("<pre class=\"x\">"),
+            Some(HtmlBlockKind::RawText)
+        );
+        assert_eq!(html_block_start("<!-- note"), Some(HtmlBlockKind::Comment));
+        assert_eq!(
+            html_block_start("<!DOCTYPE html>"),
+            Some(HtmlBlockKind::Declaration)
+        );
+        assert_eq!(
+            html_block_start("<custom-tag a=1>  "),
+            Some(HtmlBlockKind::OtherTag)
+        );
+        assert_eq!(html_block_start("<span>text</span>"), None);
+        assert_eq!(html_block_start("    <div>"), None);
+        assert_eq!(html_block_start("a <div>"), None);
+    }
+
+    #[test]
+    fn test_scan_inline_html() {
+        let scan = |s: &str| scan_inline_html(s.as_bytes(), 0, &mut UnterminatedHtml::default());
+        assert_eq!(scan("<a href=\"x\" title='y' data-z=1>rest"), Some(31));
+        assert_eq!(scan("<br/>"), Some(5));
+        assert_eq!(scan("</em >"), Some(6));
+        assert_eq!(scan("<!-- c -->x"), Some(10));
+        assert_eq!(scan("<?php x ?>"), Some(10));
+        assert_eq!(scan("<![CDATA[a]]>"), Some(13));
+        assert_eq!(scan("<a href=\"x>"), None);
+        assert_eq!(scan("<3 friends>"), None);
+        assert_eq!(scan("<a b=c=d>"), None);
+    }
+
+    #[test]
+    fn test_unterminated_inline_html() {
+        for construct in ["<!--", "<?", "<![CDATA[", "<!a", "<a b=\"", "<a b='"] {
+            let input = construct.repeat(20_000);
+            let bytes = input.as_bytes();
+            let mut unterminated = UnterminatedHtml::default();
+            let found = (0..bytes.len())
+                .filter(|&i| bytes[i] == b'<')
+                .filter_map(|i| scan_inline_html(bytes, i, &mut unterminated))
+                .count();
+            assert_eq!(found, 0, "{construct}");
+        }
+
+        // Failing from one offset doesn't hide a terminator found before it
+        let bytes = b"<!-- a <!-- b --> <!-- c";
+        let mut unterminated = UnterminatedHtml::default();
+        assert_eq!(scan_inline_html(bytes, 18, &mut unterminated), None);
+        assert_eq!(scan_inline_html(bytes, 0, &mut unterminated), Some(17));
+    }
+}
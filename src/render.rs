@@ -0,0 +1,651 @@
+use std::convert::Infallible;
+use std::fmt::Write;
+
+use crate::event::{Alignment, Event, Tag};
+#[cfg(feature = "highlight")]
+use crate::highlight::highlight_into;
+use crate::html::HtmlRenderer;
+use crate::image_size::image_size;
+use crate::options::SoftBreakStyle;
+use crate::simd::html_escape_simd_into;
+
+/// Output callbacks for each document element, driven by [`render`].
+///
+/// Every callback has a default implementation producing md2html's standard
+/// HTML, so a renderer only overrides the elements it wants to change.
+/// Elements with content are called twice, with `entering` set on the way in
+/// and cleared on the way out.
+///
+/// ```
+/// use md2html::{escape_href_into, HtmlRenderer, MarkdownParser, Renderer};
+///
+/// #[derive(Default)]
+/// struct ExternalLinks {
+///     html: HtmlRenderer,
+/// }
+///
+/// impl Renderer for ExternalLinks {
+///     fn html_renderer(&mut self) -> &mut HtmlRenderer {
+///         &mut self.html
+///     }
+///
+///     fn link(&mut self, out: &mut String, dest_url: &str, _title: &str, entering: bool) {
+///         if entering {
+///             out.push_str("<a rel=\"nofollow\" href=\"");
+///             escape_href_into(dest_url, out);
+///             out.push_str("\">");
+///         } else {
+///             out.push_str("</a>");
+///         }
+///     }
+/// }
+///
+/// let parser = MarkdownParser::new("[x](<https://example.com/a\"b>)");
+/// let html = parser.parse_with_renderer(&mut ExternalLinks::default());
+/// assert_eq!(html, "<p><a rel=\"nofollow\" href=\"https://example.com/a%22b\">x</a></p>\n");
+/// ```
+pub trait Renderer {
+    /// Settings and state shared by the default implementations.
+    fn html_renderer(&mut self) -> &mut HtmlRenderer;
+
+    /// `text` is the heading's content as plain text, e.g. for anchor ids.
+    fn heading(&mut self, out: &mut String, level: usize, text: &str, entering: bool) {
+        let _ = text;
+        if entering {
+            fresh_line(out);
+            write!(out, "<h{level}>").unwrap();
+        } else {
+            writeln!(out, "</h{level}>").unwrap();
+        }
+    }
+
+    fn paragraph(&mut self, out: &mut String, entering: bool) {
+        if entering {
+            fresh_line(out);
+            out.push_str("<p>");
+        } else {
+            out.push_str("</p>\n");
+        }
+    }
+
+    fn blockquote(&mut self, out: &mut String, entering: bool) {
+        if entering {
+            fresh_line(out);
+            out.push_str("<blockquote>\n");
+        } else {
+            out.push_str("</blockquote>\n");
+        }
+    }
+
+    /// `start` is the first number of an ordered list, `None` for bullets.
+    fn list(&mut self, out: &mut String, start: Option<u64>, entering: bool) {
+        match (start, entering) {
+            (None, true) => {
+                fresh_line(out);
+                out.push_str("<ul>\n");
+            }
+            (Some(1), true) => {
+                fresh_line(out);
+                out.push_str("<ol>\n");
+            }
+            (Some(start), true) => {
+                fresh_line(out);
+                writeln!(out, "<ol start=\"{start}\">").unwrap();
+            }
+            (None, false) => out.push_str("</ul>\n"),
+            (Some(_), false) => out.push_str("</ol>\n"),
+        }
+    }
+
+    /// `task` is whether the box of a task list item is checked, `None` for
+    /// other items.
+    fn list_item(&mut self, out: &mut String, task: Option<bool>, entering: bool) {
+        if entering {
+            let indent = self.html_renderer().list_indent;
+            out.extend(std::iter::repeat_n(' ', indent));
+            match task {
+                Some(checked) => {
+                    out.push_str("<li class=\"task-list-item\"><input type=\"checkbox\" disabled");
+                    if checked {
+                        out.push_str(" checked");
+                    }
+                    out.push_str(self.html_renderer().void_tag_end());
+                    out.push(' ');
+                }
+                None => out.push_str("<li>"),
+            }
+        } else {
+            out.push_str("</li>\n");
+        }
+    }
+
+    /// Called once with the block's complete, unescaped code. `info` is the
+    /// full info string of a fenced block, whose first word names the
+    /// language, e.g. `rust {linenos}`.
+    ///
+    /// With the `highlight` feature, code in a supported language is
+    #[cfg_attr(
+        feature = "highlight",
+        doc = "highlighted by [`highlight_into`](crate::highlight_into)."
+    )]
+    #[cfg_attr(not(feature = "highlight"), doc = "highlighted by `highlight_into`.")]
+    fn code_block(&mut self, out: &mut String, info: &str, code: &str) {
+        fresh_line(out);
+        let language = info.split_whitespace().next();
+        match language {
+            Some(language) => {
+                out.push_str("<pre><code class=\"language-");
+                html_escape_simd_into(language, out);
+                out.push_str("\">");
+            }
+            None => out.push_str("<pre><code>"),
+        }
+        #[cfg(feature = "highlight")]
+        let highlighted = language.is_some_and(|language| highlight_into(language, code, out));
+        #[cfg(not(feature = "highlight"))]
+        let highlighted = false;
+        if !highlighted {
+            html_escape_simd_into(code, out);
+        }
+        out.push_str("</code></pre>\n");
+    }
+
+    fn table(&mut self, out: &mut String, alignments: &[Alignment], entering: bool) {
+        let _ = alignments;
+        let html = self.html_renderer();
+        if entering {
+            fresh_line(out);
+            html.in_table_body = false;
+            out.push_str("<table>\n");
+        } else {
+            if html.in_table_body {
+                out.push_str("</tbody>\n");
+            }
+            out.push_str("</table>\n");
+        }
+    }
+
+    fn table_head(&mut self, out: &mut String, entering: bool) {
+        out.push_str(if entering {
+            "<thead>\n<tr>\n"
+        } else {
+            "</tr>\n</thead>\n"
+        });
+    }
+
+    fn table_row(&mut self, out: &mut String, entering: bool) {
+        if !entering {
+            out.push_str("</tr>\n");
+            return;
+        }
+        let html = self.html_renderer();
+        if !html.in_table_body {
+            html.in_table_body = true;
+            out.push_str("<tbody>\n");
+        }
+        out.push_str("<tr>\n");
+    }
+
+    /// `header` is set for the cells of the table's header row.
+    fn table_cell(&mut self, out: &mut String, alignment: Alignment, header: bool, entering: bool) {
+        if !entering {
+            out.push_str(if header { "</th>\n" } else { "</td>\n" });
+            return;
+        }
+
+        out.push_str(if header { "<th" } else { "<td" });
+        let align = match alignment {
+            Alignment::None => None,
+            Alignment::Left => Some("left"),
+            Alignment::Center => Some("center"),
+            Alignment::Right => Some("right"),
+        };
+        if let Some(align) = align {
+            match &self.html_renderer().table_alignment_class {
+                Some(prefix) => write!(out, " class=\"{prefix}{align}\"").unwrap(),
+                None => write!(out, " style=\"text-align: {align}\"").unwrap(),
+            }
+        }
+        out.push('>');
+    }
+
+    fn emphasis(&mut self, out: &mut String, entering: bool) {
+        out.push_str(if entering { "<em>" } else { "</em>" });
+    }
+
+    fn strong(&mut self, out: &mut String, entering: bool) {
+        out.push_str(if entering { "<strong>" } else { "</strong>" });
+    }
+
+    fn strikethrough(&mut self, out: &mut String, entering: bool) {
+        out.push_str(if entering { "<del>" } else { "</del>" });
+    }
+
+    fn mark(&mut self, out: &mut String, entering: bool) {
+        out.push_str(if entering { "<mark>" } else { "</mark>" });
+    }
+
+    fn superscript(&mut self, out: &mut String, entering: bool) {
+        out.push_str(if entering { "<sup>" } else { "</sup>" });
+    }
+
+    fn subscript(&mut self, out: &mut String, entering: bool) {
+        out.push_str(if entering { "<sub>" } else { "</sub>" });
+    }
+
+    /// `title` is empty if the link has none.
+    fn link(&mut self, out: &mut String, dest_url: &str, title: &str, entering: bool) {
+        if entering {
+            out.push_str("<a href=\"");
+            escape_href_into(dest_url, out);
+            out.push('"');
+            if !title.is_empty() {
+                out.push_str(" title=\"");
+                html_escape_simd_into(title, out);
+                out.push('"');
+            }
+            out.push('>');
+        } else {
+            out.push_str("</a>");
+        }
+    }
+
+    /// Called once per image, with its alt text flattened to plain text.
+    ///
+    /// The default output applies the image settings of [`ParserOptions`]:
+    /// a `src` prefix, `loading="lazy"` and the size of local files.
+    ///
+    /// [`ParserOptions`]: crate::ParserOptions
+    fn image(&mut self, out: &mut String, dest_url: &str, title: &str, alt: &str) {
+        let html = self.html_renderer();
+        let local = is_local_path(dest_url);
+
+        out.push_str("<img src=\"");
+        if let Some(prefix) = html.image_src_prefix.as_deref().filter(|_| local) {
+            escape_href_into(prefix.trim_end_matches('/'), out);
+            out.push('/');
+            escape_href_into(dest_url.trim_start_matches('/'), out);
+        } else {
+            escape_href_into(dest_url, out);
+        }
+        out.push_str("\" alt=\"");
+        html_escape_simd_into(alt, out);
+        out.push('"');
+        if !title.is_empty() {
+            out.push_str(" title=\"");
+            html_escape_simd_into(title, out);
+            out.push('"');
+        }
+        if html.lazy_images {
+            out.push_str(" loading=\"lazy\"");
+        }
+        if let Some(root) = html.image_size_root.as_ref().filter(|_| local) {
+            let path = dest_url.split(['?', '#']).next().unwrap_or_default();
+            if let Some((width, height)) = image_size(root, path) {
+                write!(out, " width=\"{width}\" height=\"{height}\"").unwrap();
+            }
+        }
+        out.push_str(html.void_tag_end());
+    }
+
+    fn code_span(&mut self, out: &mut String, code: &str) {
+        out.push_str("<code>");
+        html_escape_simd_into(code, out);
+        out.push_str("</code>");
+    }
+
+    /// Plain text, still to be escaped.
+    fn text(&mut self, out: &mut String, text: &str) {
+        html_escape_simd_into(text, out);
+    }
+
+    /// Raw HTML inside a paragraph, written through unchanged.
+    fn inline_html(&mut self, out: &mut String, html: &str) {
+        out.push_str(html);
+    }
+
+    /// A raw HTML block, written through unchanged.
+    fn html_block(&mut self, out: &mut String, html: &str) {
+        fresh_line(out);
+        out.push_str(html);
+        fresh_line(out);
+    }
+
+    fn thematic_break(&mut self, out: &mut String) {
+        fresh_line(out);
+        out.push_str("<hr");
+        out.push_str(self.html_renderer().void_tag_end());
+        out.push('\n');
+    }
+
+    /// `occurrence` counts the references to footnote `number`, from 1.
+    fn footnote_reference(&mut self, out: &mut String, number: usize, occurrence: usize) {
+        write!(out, "<sup><a href=\"#fn-{number}\" id=\"").unwrap();
+        push_footnote_reference_id(out, number, occurrence);
+        write!(out, "\">{number}</a></sup>").unwrap();
+    }
+
+    fn footnotes(&mut self, out: &mut String, entering: bool) {
+        if entering {
+            fresh_line(out);
+            out.push_str("<section class=\"footnotes\">\n<ol>\n");
+        } else {
+            out.push_str("</ol>\n</section>\n");
+        }
+    }
+
+    /// `references` is how many references to the footnote there are, each
+    /// of which gets a back-link.
+    fn footnote_definition(
+        &mut self,
+        out: &mut String,
+        number: usize,
+        references: usize,
+        entering: bool,
+    ) {
+        if entering {
+            writeln!(out, "<li id=\"fn-{number}\">").unwrap();
+            return;
+        }
+
+        let mut backrefs = String::new();
+        for occurrence in 1..=references {
+            backrefs.push_str(" <a href=\"#");
+            push_footnote_reference_id(&mut backrefs, number, occurrence);
+            write!(
+                backrefs,
+                "\" class=\"footnote-backref\" aria-label=\"Back to reference {number}\">\u{21a9}"
+            )
+            .unwrap();
+            if occurrence > 1 {
+                write!(backrefs, "<sup>{occurrence}</sup>").unwrap();
+            }
+            backrefs.push_str("</a>");
+        }
+        // The back-links go at the end of the last paragraph, if the footnote
+        // ends with one
+        match out.strip_suffix("</p>\n") {
+            Some(content) => out.insert_str(content.len(), &backrefs),
+            None => {
+                fresh_line(out);
+                out.push_str(backrefs.trim_start());
+                out.push('\n');
+            }
+        }
+        out.push_str("</li>\n");
+    }
+
+    fn soft_break(&mut self, out: &mut String) {
+        match self.html_renderer().soft_break {
+            SoftBreakStyle::Space => out.push(' '),
+            SoftBreakStyle::Newline => out.push('\n'),
+            SoftBreakStyle::HardBreak => self.hard_break(out),
+        }
+    }
+
+    fn hard_break(&mut self, out: &mut String) {
+        out.push_str("<br");
+        out.push_str(self.html_renderer().void_tag_end());
+        out.push('\n');
+    }
+}
+
+/// Writes the id of a footnote reference: `fnref-1` for the first reference
+/// to footnote 1, `fnref-1-2` for the second and so on.
+fn push_footnote_reference_id(out: &mut String, number: usize, occurrence: usize) {
+    write!(out, "fnref-{number}").unwrap();
+    if occurrence > 1 {
+        write!(out, "-{occurrence}").unwrap();
+    }
+}
+
+/// Block elements start on their own line, even when they follow the inline
+/// content of a tight list item.
+#[inline]
+fn fresh_line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Escapes `&`, `<`, `>`, `"` and `'` in `text`, so it can be written as
+/// element content or inside a quoted attribute value.
+pub fn escape_html_into(text: &str, out: &mut String) {
+    html_escape_simd_into(text, out);
+}
+
+/// Writes a URL into an `href` or `src` attribute, percent-encoding the bytes
+/// that cannot appear in a URL as is, such as spaces, quotes and non-ASCII
+/// text. Existing `%` escapes are kept.
+pub fn escape_href_into(url: &str, out: &mut String) {
+    for &b in url.as_bytes() {
+        match b {
+            b'&' => out.push_str("&amp;"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => out.push(b as char),
+            b'-' | b'_' | b'.' | b'!' | b'~' | b'*' | b'\'' | b'(' | b')' | b';' | b'/' | b'?'
+            | b':' | b'@' | b'=' | b'+' | b'$' | b',' | b'%' | b'#' => out.push(b as char),
+            _ => write!(out, "%{b:02X}").unwrap(),
+        }
+    }
+}
+
+/// Whether an image source is a path on the same site rather than a URL with
+/// a scheme (`https:`, `data:`) or host (`//cdn.example.com`).
+fn is_local_path(dest_url: &str) -> bool {
+    if dest_url.starts_with("//") {
+        return false;
+    }
+    let scheme_len = dest_url
+        .bytes()
+        .take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'))
+        .count();
+    !(scheme_len > 0
+        && dest_url.as_bytes()[0].is_ascii_alphabetic()
+        && dest_url.as_bytes().get(scheme_len) == Some(&b':'))
+}
+
+/// Drives `renderer` over an event stream, appending its output to `output`.
+pub fn render<'a, R, I>(renderer: &mut R, output: &mut String, events: I)
+where
+    R: Renderer + ?Sized,
+    I: IntoIterator<Item = Event<'a>>,
+{
+    let result: Result<(), Infallible> = render_blocks(renderer, output, events, |_| Ok(()));
+    match result {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
+}
+
+/// Like [`render`], but calls `flush` with `output` after each top-level
+/// block, so a caller can write the block out and clear the buffer.
+pub(crate) fn render_blocks<'a, R, I, E, F>(
+    renderer: &mut R,
+    output: &mut String,
+    events: I,
+    mut flush: F,
+) -> Result<(), E>
+where
+    R: Renderer + ?Sized,
+    I: IntoIterator<Item = Event<'a>>,
+    F: FnMut(&mut String) -> Result<(), E>,
+{
+    let mut driver = Driver {
+        renderer,
+        output,
+        table_alignments: Vec::new(),
+        table_cell_index: 0,
+        in_table_head: false,
+        image: None,
+    };
+    let mut events = events.into_iter();
+    let mut depth = 0usize;
+
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(Tag::Heading(level)) => {
+                // Headings only hold inline content, so buffering them up to
+                // their end is cheap and lets the callback see their text.
+                let content: Vec<Event<'a>> = events
+                    .by_ref()
+                    .take_while(|event| !matches!(event, Event::End(Tag::Heading(_))))
+                    .collect();
+                let text = plain_text(&content);
+                driver.renderer.heading(driver.output, level, &text, true);
+                for event in content {
+                    driver.event(event);
+                }
+                driver.renderer.heading(driver.output, level, &text, false);
+            }
+            Event::Start(Tag::CodeBlock(info)) => {
+                let mut code = String::new();
+                for event in events.by_ref() {
+                    match event {
+                        Event::Text(text) => code.push_str(&text),
+                        Event::End(Tag::CodeBlock(_)) => break,
+                        _ => {}
+                    }
+                }
+                driver.renderer.code_block(driver.output, &info, &code);
+            }
+            event => {
+                match event {
+                    Event::Start(_) => depth += 1,
+                    Event::End(_) => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                driver.event(event);
+            }
+        }
+
+        if depth == 0 {
+            flush(driver.output)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn plain_text(events: &[Event<'_>]) -> String {
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::Text(content) | Event::Code(content) => text.push_str(content),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+struct Driver<'r, 'o, R: Renderer + ?Sized> {
+    renderer: &'r mut R,
+    output: &'o mut String,
+    table_alignments: Vec<Alignment>,
+    table_cell_index: usize,
+    in_table_head: bool,
+    /// The image being rendered, whose alt text is collected up to its end
+    image: Option<PendingImage>,
+}
+
+struct PendingImage {
+    dest_url: String,
+    title: String,
+    alt: String,
+    /// Images nested in the alt text, which only contribute their own alt text
+    nested: usize,
+}
+
+impl<R: Renderer + ?Sized> Driver<'_, '_, R> {
+    fn event(&mut self, event: Event<'_>) {
+        let out = &mut *self.output;
+        if let Some(image) = &mut self.image {
+            match event {
+                Event::Start(Tag::Image { .. }) => image.nested += 1,
+                Event::End(Tag::Image { .. }) if image.nested > 0 => image.nested -= 1,
+                Event::End(Tag::Image { .. }) => {
+                    let image = self.image.take().unwrap();
+                    self.renderer
+                        .image(out, &image.dest_url, &image.title, &image.alt);
+                }
+                Event::Text(text) | Event::Code(text) => image.alt.push_str(&text),
+                Event::SoftBreak | Event::HardBreak => image.alt.push(' '),
+                _ => {}
+            }
+            return;
+        }
+
+        match event {
+            Event::Start(tag) => self.tag(tag, true),
+            Event::End(tag) => self.tag(tag, false),
+            Event::Text(text) => self.renderer.text(out, &text),
+            Event::Code(code) => self.renderer.code_span(out, &code),
+            Event::Html(html) => self.renderer.html_block(out, &html),
+            Event::InlineHtml(html) => self.renderer.inline_html(out, &html),
+            Event::FootnoteReference {
+                number, occurrence, ..
+            } => self.renderer.footnote_reference(out, number, occurrence),
+            Event::SoftBreak => self.renderer.soft_break(out),
+            Event::HardBreak => self.renderer.hard_break(out),
+            Event::Rule => self.renderer.thematic_break(out),
+        }
+    }
+
+    fn tag(&mut self, tag: Tag<'_>, entering: bool) {
+        let out = &mut *self.output;
+        match tag {
+            Tag::Paragraph => self.renderer.paragraph(out, entering),
+            // Buffered and rendered as a whole by `render`
+            Tag::Heading(_) | Tag::CodeBlock(_) => {}
+            Tag::BlockQuote => self.renderer.blockquote(out, entering),
+            Tag::List(start) => self.renderer.list(out, start, entering),
+            Tag::Item(task) => self.renderer.list_item(out, task, entering),
+            Tag::Table(alignments) => {
+                self.renderer.table(out, &alignments, entering);
+                self.table_alignments = alignments;
+            }
+            Tag::TableHead => {
+                self.in_table_head = entering;
+                self.table_cell_index = 0;
+                self.renderer.table_head(out, entering);
+            }
+            Tag::TableRow => {
+                self.table_cell_index = 0;
+                self.renderer.table_row(out, entering);
+            }
+            Tag::TableCell => {
+                let alignment = self
+                    .table_alignments
+                    .get(self.table_cell_index)
+                    .copied()
+                    .unwrap_or(Alignment::None);
+                self.renderer
+                    .table_cell(out, alignment, self.in_table_head, entering);
+                if !entering {
+                    self.table_cell_index += 1;
+                }
+            }
+            Tag::Emphasis => self.renderer.emphasis(out, entering),
+            Tag::Strong => self.renderer.strong(out, entering),
+            Tag::Strikethrough => self.renderer.strikethrough(out, entering),
+            Tag::Mark => self.renderer.mark(out, entering),
+            Tag::Superscript => self.renderer.superscript(out, entering),
+            Tag::Subscript => self.renderer.subscript(out, entering),
+            Tag::Link { dest_url, title } => self.renderer.link(out, &dest_url, &title, entering),
+            Tag::Footnotes => self.renderer.footnotes(out, entering),
+            Tag::FootnoteDefinition { number, references } => self
+                .renderer
+                .footnote_definition(out, number, references, entering),
+            // Rendered once the alt text is complete, see `event`
+            Tag::Image { dest_url, title } if entering => {
+                self.image = Some(PendingImage {
+                    dest_url: dest_url.into_owned(),
+                    title: title.into_owned(),
+                    alt: String::new(),
+                    nested: 0,
+                });
+            }
+            Tag::Image { .. } => {}
+        }
+    }
+}
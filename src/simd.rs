@@ -11,64 +11,40 @@ pub fn html_escape_simd_into(text: &str, output: &mut String) {
     let bytes = text.as_bytes();
     let mut start = 0;
 
-    // Use SIMD to find ALL HTML special characters at once: & < > " '
-    // memchr can search for multiple characters in parallel using SIMD instructions
+    // memchr searches for at most three bytes at once, so look for & < > and
+    // for the quotes separately and escape whichever comes first. Each search
+    // is only repeated once its match has been escaped, so neither rescans
+    // the rest of the text for every character the other finds.
+    let find_markup = |from: usize| memchr3(b'&', b'<', b'>', &bytes[from..]).map(|i| from + i);
+    let find_quote = |from: usize| memchr2(b'"', b'\'', &bytes[from..]).map(|i| from + i);
+    let mut markup = find_markup(0);
+    let mut quote = find_quote(0);
     loop {
-        // SIMD search for any special character starting from 'start'
-        match memchr3(b'&', b'<', b'>', &bytes[start..]) {
-            Some(pos) => {
-                let abs_pos = start + pos;
-
-                // Copy clean text before special character (if any)
-                if start < abs_pos {
-                    // SAFETY: We know this is valid UTF-8 as it's a slice of the original string
-                    output
-                        .push_str(unsafe { std::str::from_utf8_unchecked(&bytes[start..abs_pos]) });
-                }
-
-                // Handle the special character
-                match bytes[abs_pos] {
-                    b'&' => output.push_str("&amp;"),
-                    b'<' => output.push_str("&lt;"),
-                    b'>' => output.push_str("&gt;"),
-                    _ => unreachable!(),
-                }
-
-                start = abs_pos + 1;
-            }
-            None => {
-                // No more &, <, > found. Now check for " and '
-                match memchr2(b'"', b'\'', &bytes[start..]) {
-                    Some(pos) => {
-                        let abs_pos = start + pos;
-
-                        // Copy clean text before special character
-                        if start < abs_pos {
-                            output.push_str(unsafe {
-                                std::str::from_utf8_unchecked(&bytes[start..abs_pos])
-                            });
-                        }
-
-                        // Handle quote characters
-                        match bytes[abs_pos] {
-                            b'"' => output.push_str("&quot;"),
-                            b'\'' => output.push_str("&#39;"),
-                            _ => unreachable!(),
-                        }
-
-                        start = abs_pos + 1;
-                    }
-                    None => {
-                        // No special characters remaining, copy the rest
-                        if start < bytes.len() {
-                            output.push_str(unsafe {
-                                std::str::from_utf8_unchecked(&bytes[start..])
-                            });
-                        }
-                        break;
-                    }
-                }
+        let pos = match (markup, quote) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(pos), None) | (None, Some(pos)) => pos,
+            (None, None) => {
+                // No special characters remaining, copy the rest
+                output.push_str(&text[start..]);
+                break;
             }
+        };
+
+        // Special characters are ASCII, so `pos` is always a char boundary
+        output.push_str(&text[start..pos]);
+        output.push_str(match bytes[pos] {
+            b'&' => "&amp;",
+            b'<' => "&lt;",
+            b'>' => "&gt;",
+            b'"' => "&quot;",
+            b'\'' => "&#39;",
+            _ => unreachable!(),
+        });
+        start = pos + 1;
+        if markup == Some(pos) {
+            markup = find_markup(start);
+        } else {
+            quote = find_quote(start);
         }
     }
 }
@@ -92,6 +68,12 @@ pub fn detect_line_type_simd(line: &str) -> LineType {
     if trimmed.is_empty() {
         return LineType::Empty;
     }
+    if indent_columns(line) >= 4 {
+        return LineType::IndentedCode;
+    }
+    if is_thematic_break(trimmed.as_bytes()) {
+        return LineType::ThematicBreak;
+    }
 
     let bytes = trimmed.as_bytes();
 
@@ -105,14 +87,16 @@ pub fn detect_line_type_simd(line: &str) -> LineType {
 
     // Fast list detection
     if bytes.len() >= 2 {
-        if let (b'-' | b'*' | b'+', b' ') = (bytes[0], bytes[1]) {
+        if let (b'-' | b'*' | b'+', b' ' | b'\t') = (bytes[0], bytes[1]) {
             return LineType::UnorderedList;
         }
 
-        // Optimized ordered list detection using SIMD
+        // Optimized ordered list detection using SIMD, for up to nine digits
         if bytes[0].is_ascii_digit() {
-            if let Some(dot_pos) = memchr(b'.', bytes) {
-                if dot_pos > 0 && bytes.get(dot_pos + 1) == Some(&b' ') {
+            if let Some(dot_pos) = memchr2(b'.', b')', bytes) {
+                if (1..=9).contains(&dot_pos)
+                    && matches!(bytes.get(dot_pos + 1), Some(b' ' | b'\t'))
+                {
                     // Use SIMD byte counting to verify all digits
                     if is_all_digits_simd(&bytes[..dot_pos]) {
                         return LineType::OrderedList;
@@ -127,6 +111,11 @@ pub fn detect_line_type_simd(line: &str) -> LineType {
         return LineType::CodeBlock;
     }
 
+    // Fast block quote detection
+    if bytes[0] == b'>' {
+        return LineType::BlockQuote;
+    }
+
     LineType::Paragraph
 }
 
@@ -137,9 +126,46 @@ pub enum LineType {
     UnorderedList,
     OrderedList,
     CodeBlock,
+    /// Indented by four or more columns
+    IndentedCode,
+    /// `---`, `***` or `___`, optionally spaced out
+    ThematicBreak,
+    BlockQuote,
     Paragraph,
 }
 
+/// Three or more `-`, `*` or `_` characters, all the same, with nothing else
+/// on the line but spaces and tabs. Checked before list items, so `- - -` is
+/// a break rather than a bullet.
+pub fn is_thematic_break(bytes: &[u8]) -> bool {
+    let Some(&marker @ (b'-' | b'*' | b'_')) = bytes.first() else {
+        return false;
+    };
+    let mut count = 0;
+    for &byte in bytes {
+        match byte {
+            b' ' | b'\t' => {}
+            b if b == marker => count += 1,
+            _ => return false,
+        }
+    }
+    count >= 3
+}
+
+/// Width of the line's leading whitespace, with tabs advancing to the next
+/// multiple of four columns.
+pub fn indent_columns(line: &str) -> usize {
+    let mut columns = 0;
+    for byte in line.bytes() {
+        match byte {
+            b' ' => columns += 1,
+            b'\t' => columns += 4 - columns % 4,
+            _ => break,
+        }
+    }
+    columns
+}
+
 /// SIMD helper functions
 #[cfg(feature = "simd")]
 #[inline]
@@ -173,15 +199,20 @@ fn is_all_digits_simd(bytes: &[u8]) -> bool {
 #[cfg(feature = "simd")]
 #[inline]
 fn is_code_block_start_simd(bytes: &[u8]) -> bool {
-    // Check for ``` pattern - could be optimized with SIMD for longer patterns
-    bytes.len() >= 3 && bytes[0] == b'`' && bytes[1] == b'`' && bytes[2] == b'`'
+    // A run of at least three backticks or tildes. The info string after a
+    // backtick fence can't contain backticks, or it would be a code span.
+    let Some(&marker @ (b'`' | b'~')) = bytes.first() else {
+        return false;
+    };
+    let len = count_leading_bytes_simd(bytes, marker);
+    len >= 3 && (marker == b'~' || memchr(b'`', &bytes[len..]).is_none())
 }
 
 // =================== FALLBACK IMPLEMENTATIONS (No SIMD) ===================
 
 #[cfg(not(feature = "simd"))]
 pub fn html_escape_simd_into(text: &str, output: &mut String) {
-    html_escape_scalar_into(text.as_bytes(), output);
+    html_escape_scalar_into(text, output);
 }
 
 #[cfg(not(feature = "simd"))]
@@ -198,6 +229,12 @@ pub fn detect_line_type_simd(line: &str) -> LineType {
     if trimmed.is_empty() {
         return LineType::Empty;
     }
+    if indent_columns(line) >= 4 {
+        return LineType::IndentedCode;
+    }
+    if is_thematic_break(trimmed.as_bytes()) {
+        return LineType::ThematicBreak;
+    }
 
     let bytes = trimmed.as_bytes();
 
@@ -209,14 +246,15 @@ pub fn detect_line_type_simd(line: &str) -> LineType {
 
     // List detection
     if bytes.len() >= 2 {
-        if let (b'-' | b'*' | b'+', b' ') = (bytes[0], bytes[1]) {
+        if let (b'-' | b'*' | b'+', b' ' | b'\t') = (bytes[0], bytes[1]) {
             return LineType::UnorderedList;
         }
 
+        // Ordered lists number their items with up to nine digits
         if bytes[0].is_ascii_digit() {
-            if let Some(dot_pos) = bytes.iter().position(|&b| b == b'.') {
-                if dot_pos > 0
-                    && bytes.get(dot_pos + 1) == Some(&b' ')
+            if let Some(dot_pos) = bytes.iter().position(|&b| matches!(b, b'.' | b')')) {
+                if (1..=9).contains(&dot_pos)
+                    && matches!(bytes.get(dot_pos + 1), Some(b' ' | b'\t'))
                     && bytes[..dot_pos].iter().all(|&b| b.is_ascii_digit())
                 {
                     return LineType::OrderedList;
@@ -225,9 +263,18 @@ pub fn detect_line_type_simd(line: &str) -> LineType {
         }
     }
 
-    // Code block detection
-    if bytes.len() >= 3 && &bytes[0..3] == b"```" {
-        return LineType::CodeBlock;
+    // Code block detection: three or more backticks or tildes, and no
+    // backticks in the info string of a backtick fence
+    if let marker @ (b'`' | b'~') = bytes[0] {
+        let len = bytes.iter().take_while(|&&b| b == marker).count();
+        if len >= 3 && (marker == b'~' || !bytes[len..].contains(&b'`')) {
+            return LineType::CodeBlock;
+        }
+    }
+
+    // Block quote detection
+    if bytes[0] == b'>' {
+        return LineType::BlockQuote;
     }
 
     LineType::Paragraph
@@ -235,17 +282,23 @@ pub fn detect_line_type_simd(line: &str) -> LineType {
 
 /// Scalar fallback implementation
 #[cfg(not(feature = "simd"))]
-fn html_escape_scalar_into(bytes: &[u8], output: &mut String) {
-    for &byte in bytes {
-        match byte {
-            b'&' => output.push_str("&amp;"),
-            b'<' => output.push_str("&lt;"),
-            b'>' => output.push_str("&gt;"),
-            b'"' => output.push_str("&quot;"),
-            b'\'' => output.push_str("&#39;"),
-            _ => output.push(byte as char),
-        }
+fn html_escape_scalar_into(text: &str, output: &mut String) {
+    let mut start = 0;
+    for (i, byte) in text.bytes().enumerate() {
+        let escaped = match byte {
+            b'&' => "&amp;",
+            b'<' => "&lt;",
+            b'>' => "&gt;",
+            b'"' => "&quot;",
+            b'\'' => "&#39;",
+            _ => continue,
+        };
+        // Special characters are ASCII, so `i` is always a char boundary
+        output.push_str(&text[start..i]);
+        output.push_str(escaped);
+        start = i + 1;
     }
+    output.push_str(&text[start..]);
 }
 
 #[cfg(test)]
@@ -262,6 +315,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_html_escape_simd_quote_before_markup() {
+        let mut output = String::new();
+        html_escape_simd_into("a='1' & \"b\">", &mut output);
+        assert_eq!(output, "a=&#39;1&#39; &amp; &quot;b&quot;&gt;");
+    }
+
+    #[test]
+    fn test_html_escape_simd_interleaved() {
+        let text = "<<'x'\"é&>".repeat(1000) + "tail";
+        let expected = text
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&#39;");
+        let mut output = String::new();
+        html_escape_simd_into(&text, &mut output);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_html_escape_simd_non_ascii() {
+        let mut output = String::new();
+        html_escape_simd_into("1.89 µs & 🚀", &mut output);
+        assert_eq!(output, "1.89 µs &amp; 🚀");
+    }
+
     #[test]
     fn test_html_escape_simd_no_special() {
         let mut output = String::new();
@@ -286,8 +367,33 @@ mod tests {
         assert_eq!(detect_line_type_simd("+ Item"), LineType::UnorderedList);
         assert_eq!(detect_line_type_simd("1. Item"), LineType::OrderedList);
         assert_eq!(detect_line_type_simd("42. Item"), LineType::OrderedList);
+        assert_eq!(detect_line_type_simd("3) Item"), LineType::OrderedList);
+        assert_eq!(detect_line_type_simd("1.\tItem"), LineType::OrderedList);
+        assert_eq!(detect_line_type_simd("-\tItem"), LineType::UnorderedList);
+        assert_eq!(detect_line_type_simd("1)Item"), LineType::Paragraph);
+        assert_eq!(
+            detect_line_type_simd("123456789. Item"),
+            LineType::OrderedList
+        );
+        assert_eq!(
+            detect_line_type_simd("1234567890. Item"),
+            LineType::Paragraph
+        );
         assert_eq!(detect_line_type_simd("```"), LineType::CodeBlock);
         assert_eq!(detect_line_type_simd("```rust"), LineType::CodeBlock);
+        assert_eq!(detect_line_type_simd("~~~"), LineType::CodeBlock);
+        assert_eq!(detect_line_type_simd("    code"), LineType::IndentedCode);
+        assert_eq!(detect_line_type_simd("- - -"), LineType::ThematicBreak);
+        assert_eq!(detect_line_type_simd("  ***"), LineType::ThematicBreak);
+        assert_eq!(detect_line_type_simd("__ _ "), LineType::ThematicBreak);
+        assert_eq!(detect_line_type_simd("-- *"), LineType::Paragraph);
+        assert_eq!(detect_line_type_simd("- -"), LineType::UnorderedList);
+        assert_eq!(detect_line_type_simd("  \t# code"), LineType::IndentedCode);
+        assert_eq!(detect_line_type_simd("   # heading"), LineType::Header(1));
+        assert_eq!(detect_line_type_simd("```a`"), LineType::Paragraph);
+        assert_eq!(detect_line_type_simd("> Quote"), LineType::BlockQuote);
+        assert_eq!(detect_line_type_simd(">> Nested"), LineType::BlockQuote);
+        assert_eq!(detect_line_type_simd(">"), LineType::BlockQuote);
         assert_eq!(detect_line_type_simd("Regular text"), LineType::Paragraph);
         assert_eq!(detect_line_type_simd(""), LineType::Empty);
         assert_eq!(detect_line_type_simd("   "), LineType::Empty);
@@ -308,5 +414,7 @@ mod tests {
         assert!(is_code_block_start_simd(b"```"));
         assert!(!is_code_block_start_simd(b"``"));
         assert!(!is_code_block_start_simd(b"`code`"));
+        assert!(is_code_block_start_simd(b"~~~~ toml"));
+        assert!(!is_code_block_start_simd(b"``` a`b"));
     }
 }
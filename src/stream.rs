@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead};
+
+use crate::render::{render, render_blocks};
+use crate::{normalize_label, HtmlRenderer, LinkDefinition, MarkdownParser, ParserOptions};
+
+/// Converts Markdown read line by line from an [`io::BufRead`], created with
+/// [`MarkdownParser::from_reader`].
+///
+/// Only the lines of the block currently being parsed are kept in memory.
+/// A top-level block is rendered as soon as the line that ends it has been
+/// read, so input of any length can be converted as it arrives:
+///
+/// ```
+/// use md2html::MarkdownParser;
+///
+/// let input = "# Log\n\nfirst entry\n\nsecond entry\n";
+/// let mut html = Vec::new();
+/// MarkdownParser::from_reader(input.as_bytes())
+///     .render_to_writer(&mut html)
+///     .unwrap();
+/// assert_eq!(html, b"<h1>Log</h1>\n<p>first entry</p>\n<p>second entry</p>\n");
+/// ```
+///
+/// Since blocks are written before the rest of the input is read, reference
+/// links only resolve to definitions that come before them, and footnotes,
+/// which are listed at the end of the document, are not recognised.
+pub struct ReaderParser<R> {
+    reader: R,
+    options: ParserOptions,
+    /// Link reference definitions supplied up front or read so far
+    definitions: HashMap<String, LinkDefinition<'static>>,
+    /// Labels defined in the input, whose first definition is kept
+    defined_labels: HashSet<String>,
+}
+
+impl<R: BufRead> ReaderParser<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, ParserOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: ParserOptions) -> Self {
+        Self {
+            reader,
+            options,
+            definitions: HashMap::new(),
+            defined_labels: HashSet::new(),
+        }
+    }
+
+    /// Adds a definition for reference links, like
+    /// [`MarkdownParser::with_link_definition`].
+    pub fn with_link_definition(
+        mut self,
+        label: &str,
+        definition: LinkDefinition<'static>,
+    ) -> Self {
+        self.definitions.insert(normalize_label(label), definition);
+        self
+    }
+
+    /// Reads the input to its end, writing the HTML of each top-level block
+    /// to `writer` once it is complete.
+    pub fn render_to_writer<W: io::Write>(mut self, mut writer: W) -> io::Result<()> {
+        let mut renderer = HtmlRenderer::with_options(&self.options);
+        let mut output = String::new();
+        let mut write_block = |block: &mut String| {
+            writer.write_all(block.as_bytes())?;
+            block.clear();
+            Ok::<(), io::Error>(())
+        };
+
+        // Lines of the blocks not yet rendered, each ending in a newline
+        let mut pending = String::new();
+        let mut line_count = 0;
+        let mut next_attempt = 1;
+
+        loop {
+            let line_start = pending.len();
+            if self.reader.read_line(&mut pending)? == 0 {
+                break;
+            }
+            if !pending.ends_with('\n') {
+                pending.push('\n');
+            }
+
+            // Only a non-blank line can end a block, since blank lines may
+            // still be followed by more content of the same list or quote
+            if pending[line_start..].trim().is_empty() {
+                if line_count == 0 {
+                    pending.clear();
+                } else {
+                    line_count += 1;
+                }
+                continue;
+            }
+            line_count += 1;
+
+            // Re-parsing the pending lines after every line would make long
+            // blocks quadratic, so attempts thin out as a block grows
+            if line_count < next_attempt {
+                continue;
+            }
+
+            while let Some(end) = self.complete_block(&pending, &mut renderer, &mut output) {
+                write_block(&mut output)?;
+                pending.drain(..end);
+            }
+            line_count = pending.lines().count();
+            next_attempt = line_count + 1 + line_count / 16;
+        }
+
+        let parser = MarkdownParser::streaming(&pending, self.options.clone(), &self.definitions);
+        render_blocks(&mut renderer, &mut output, parser.events(), write_block)?;
+        writer.flush()
+    }
+
+    /// Renders the first block of `pending` into `output` if a later non-blank
+    /// line shows that it has ended, returning the byte offset at which the
+    /// remaining lines start.
+    fn complete_block(
+        &mut self,
+        pending: &str,
+        renderer: &mut HtmlRenderer,
+        output: &mut String,
+    ) -> Option<usize> {
+        let parser = MarkdownParser::streaming(pending, self.options.clone(), &self.definitions);
+        let lines: Vec<&str> = pending.lines().collect();
+        let mut events = Vec::new();
+        let consumed = parser.parse_block(&lines, &mut events);
+
+        let next = lines[consumed..]
+            .iter()
+            .find(|line| !line.trim().is_empty())?;
+
+        render(renderer, output, events);
+        let defined: Vec<_> = parser
+            .into_definitions()
+            .into_iter()
+            .map(|(label, definition)| (label, definition.into_owned()))
+            .collect();
+        for (label, definition) in defined {
+            if self.defined_labels.insert(label.clone()) {
+                self.definitions.insert(label, definition);
+            }
+        }
+        Some(next.as_ptr() as usize - pending.as_ptr() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn convert(input: &str) -> String {
+        let mut html = Vec::new();
+        MarkdownParser::from_reader(input.as_bytes())
+            .render_to_writer(&mut html)
+            .unwrap();
+        String::from_utf8(html).unwrap()
+    }
+
+    #[test]
+    fn test_matches_in_memory_parser() {
+        let inputs = [
+            "",
+            "\n\n# Title\n\ntext\nmore text\n\n\n",
+            "- a\n\n- b\n  continued\n\n  second paragraph\nlazy\n\nafter\n",
+            "> quote\nlazy\n\n> another\n\n1. one\n2. two\n",
+            "```\ncode\n\nmore\n```\nafter\n",
+            "| a | b |\n|---|:-:|\n| 1 | 2 |\nnext\n",
+            "no trailing newline",
+            "line one\r\nline two\r\n\r\n# h\r\n",
+        ];
+        for input in inputs {
+            assert_eq!(
+                convert(input),
+                MarkdownParser::new(input).parse(),
+                "{input:?}"
+            );
+        }
+
+        let long_code = format!("```\n{}```\n\ntext\n", "line\n".repeat(500));
+        assert_eq!(convert(&long_code), MarkdownParser::new(&long_code).parse());
+    }
+
+    #[test]
+    fn test_references_resolve_to_earlier_definitions() {
+        let input = "[early]: /early\n\n[early] [late]\n\n[late]: /late\n\n[late]\n";
+        assert_eq!(
+            convert(input),
+            "<p><a href=\"/early\">early</a> [late]</p>\n<p><a href=\"/late\">late</a></p>\n"
+        );
+
+        let mut html = Vec::new();
+        MarkdownParser::from_reader("[x] [y]\n\n[y]: /doc-y\n\n[y]\n".as_bytes())
+            .with_link_definition(
+                "X",
+                LinkDefinition {
+                    dest_url: "/x".into(),
+                    title: "".into(),
+                },
+            )
+            .with_link_definition(
+                "y",
+                LinkDefinition {
+                    dest_url: "/seeded-y".into(),
+                    title: "".into(),
+                },
+            )
+            .render_to_writer(&mut html)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            "<p><a href=\"/x\">x</a> <a href=\"/seeded-y\">y</a></p>\n<p><a href=\"/doc-y\">y</a></p>\n"
+        );
+    }
+
+    /// Hands out one line per read, logging the reads and writes in order.
+    struct Lines {
+        lines: Vec<&'static str>,
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl io::Read for Lines {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = io::BufRead::fill_buf(self)?.len().min(buf.len());
+            buf[..n].copy_from_slice(&self.lines[0].as_bytes()[..n]);
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for Lines {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            Ok(self.lines.first().map_or(&[], |line| line.as_bytes()))
+        }
+
+        fn consume(&mut self, amount: usize) {
+            if amount == 0 {
+                return;
+            }
+            let line = self.lines.remove(0);
+            assert_eq!(amount, line.len());
+            self.log.borrow_mut().push(format!("read {line:?}"));
+        }
+    }
+
+    struct Log(Rc<RefCell<Vec<String>>>);
+
+    impl io::Write for Log {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let html = String::from_utf8(buf.to_vec()).unwrap();
+            self.0.borrow_mut().push(format!("write {html:?}"));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_blocks_written_once_ended() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let reader = Lines {
+            lines: vec!["first\n", "\n", "- a\n", "\n", "- b\n", "next\n"],
+            log: Rc::clone(&log),
+        };
+        MarkdownParser::from_reader(reader)
+            .render_to_writer(Log(Rc::clone(&log)))
+            .unwrap();
+
+        assert_eq!(
+            *log.borrow(),
+            [
+                "read \"first\\n\"",
+                "read \"\\n\"",
+                "read \"- a\\n\"",
+                "write \"<p>first</p>\\n\"",
+                "read \"\\n\"",
+                "read \"- b\\n\"",
+                "read \"next\\n\"",
+                "write \"<ul>\\n  <li>\\n<p>a</p>\\n</li>\\n  <li>\\n<p>b\\nnext</p>\\n</li>\\n</ul>\\n\"",
+            ]
+        );
+    }
+}
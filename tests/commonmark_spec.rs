@@ -0,0 +1,167 @@
+//! Runs the 652 examples of the CommonMark 0.31.2 spec (`tests/spec/spec.json`)
+//! and compares the results against the allowlist of known failures in
+//! `tests/spec/failing.txt`.
+//!
+//! The test fails if an example outside the allowlist fails, so regressions are
+//! caught, and also if an allowlisted example starts passing, so the allowlist
+//! keeps tracking progress. After a deliberate change, regenerate it with:
+//!
+//! ```text
+//! UPDATE_SPEC_ALLOWLIST=1 cargo test --test commonmark_spec
+//! ```
+//!
+//! Run with `-- --nocapture` to see the pass rate of every section.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+use md2html::{MarkdownParser, ParserOptions, SoftBreakStyle};
+
+const SPEC: &str = "tests/spec/spec.json";
+const ALLOWLIST: &str = "tests/spec/failing.txt";
+
+struct Example {
+    number: u64,
+    section: String,
+    markdown: String,
+    html: String,
+}
+
+fn load_examples() -> Vec<Example> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(SPEC);
+    let json = fs::read_to_string(path).expect("spec.json should be readable");
+    let value: serde_json::Value = serde_json::from_str(&json).expect("spec.json should be valid");
+    let field = |example: &serde_json::Value, name: &str| {
+        example[name]
+            .as_str()
+            .unwrap_or_else(|| panic!("example is missing `{name}`"))
+            .to_string()
+    };
+
+    value
+        .as_array()
+        .expect("spec.json should hold an array of examples")
+        .iter()
+        .map(|example| Example {
+            number: example["example"].as_u64().expect("example number"),
+            section: field(example, "section"),
+            markdown: field(example, "markdown"),
+            html: field(example, "html"),
+        })
+        .collect()
+}
+
+fn load_allowlist() -> BTreeSet<u64> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(ALLOWLIST);
+    let Ok(list) = fs::read_to_string(path) else {
+        return BTreeSet::new();
+    };
+    list.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse().expect("allowlist entries are example numbers"))
+        .collect()
+}
+
+/// The spec's HTML without tables or GFM extensions, and without the
+/// indentation md2html puts before list items.
+fn spec_options() -> ParserOptions {
+    ParserOptions::new()
+        .tables(false)
+        .raw_html(true)
+        .soft_break(SoftBreakStyle::Newline)
+        .list_indent(0)
+        .xhtml(true)
+}
+
+/// Ignores line breaks between tags, which don't affect the rendered page.
+fn normalize(html: &str) -> String {
+    let mut normalized = String::with_capacity(html.len());
+    let mut rest = html.trim();
+    while let Some(pos) = rest.find(">\n") {
+        normalized.push_str(&rest[..=pos]);
+        rest = &rest[pos + 1..];
+        let after = rest.trim_start_matches('\n');
+        if after.starts_with('<') {
+            rest = after;
+        }
+    }
+    normalized.push_str(rest);
+    normalized
+}
+
+fn passes(example: &Example) -> bool {
+    let result = panic::catch_unwind(|| {
+        MarkdownParser::with_options(&example.markdown, spec_options()).parse()
+    });
+    result.is_ok_and(|html| normalize(&html) == normalize(&example.html))
+}
+
+#[test]
+fn commonmark_spec() {
+    let examples = load_examples();
+    let allowlist = load_allowlist();
+
+    // Panics are counted as failures, so keep their messages out of the report
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results: Vec<(&Example, bool)> = examples.iter().map(|e| (e, passes(e))).collect();
+    panic::set_hook(default_hook);
+
+    let mut report = String::new();
+    let mut sections: Vec<(&str, usize, usize)> = Vec::new();
+    for (example, passed) in &results {
+        match sections.last_mut() {
+            Some((section, total, pass)) if *section == example.section => {
+                *total += 1;
+                *pass += usize::from(*passed);
+            }
+            _ => sections.push((&example.section, 1, usize::from(*passed))),
+        }
+    }
+    for (section, total, pass) in &sections {
+        writeln!(report, "{section:<42} {pass:>3}/{total:<3}").unwrap();
+    }
+    let passed = results.iter().filter(|(_, passed)| *passed).count();
+    writeln!(report, "{:<42} {passed:>3}/{}", "Total", results.len()).unwrap();
+    println!("{report}");
+
+    let failing: BTreeSet<u64> = results
+        .iter()
+        .filter(|(_, passed)| !passed)
+        .map(|(example, _)| example.number)
+        .collect();
+
+    if std::env::var_os("UPDATE_SPEC_ALLOWLIST").is_some() {
+        let mut list = String::from(
+            "# CommonMark spec examples md2html does not pass yet, grouped by section.\n\
+             # Regenerate with `UPDATE_SPEC_ALLOWLIST=1 cargo test --test commonmark_spec`.\n",
+        );
+        let mut section = "";
+        for (example, passed) in &results {
+            if *passed {
+                continue;
+            }
+            if example.section != section {
+                section = &example.section;
+                write!(list, "\n# {section}\n").unwrap();
+            }
+            writeln!(list, "{}", example.number).unwrap();
+        }
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(ALLOWLIST);
+        fs::write(path, list).expect("allowlist should be writable");
+        return;
+    }
+
+    let regressions: Vec<_> = failing.difference(&allowlist).collect();
+    let fixed: Vec<_> = allowlist.difference(&failing).collect();
+    assert!(
+        regressions.is_empty() && fixed.is_empty(),
+        "spec results differ from {ALLOWLIST}\n\
+         newly failing examples: {regressions:?}\n\
+         newly passing examples (remove them from the allowlist): {fixed:?}"
+    );
+}
@@ -0,0 +1,401 @@
+//! Differential test against pulldown-cmark and comrak.
+//!
+//! A corpus made of the `benchmark/samples/*.md` documents, split into their
+//! top-level blocks, plus generated snippets for each construct is rendered by
+//! all three parsers. After normalising whitespace, every input where
+//! md2html's output differs from a reference implementation is counted against
+//! its construct.
+//!
+//! The summary is printed (run with `-- --nocapture`) and the full report,
+//! with the input and the three outputs of every divergence, is written to
+//! `differential-report.md` in Cargo's test scratch directory. Divergences
+//! don't fail the test; panics in md2html do.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use md2html::{MarkdownParser, ParserOptions, SoftBreakStyle};
+
+struct Case {
+    construct: String,
+    source: String,
+    markdown: String,
+}
+
+/// The outputs of the three parsers for one input.
+struct Outputs {
+    md2html: String,
+    pulldown: String,
+    comrak: String,
+}
+
+#[derive(Default)]
+struct Tally {
+    cases: usize,
+    pulldown: usize,
+    comrak: usize,
+    both: usize,
+}
+
+fn md2html_options() -> ParserOptions {
+    // Match the reference parsers' defaults as closely as the options allow
+    ParserOptions::new()
+        .raw_html(true)
+        .soft_break(SoftBreakStyle::Newline)
+        .list_indent(0)
+        .xhtml(true)
+}
+
+fn render_pulldown(markdown: &str) -> String {
+    let parser = pulldown_cmark::Parser::new_ext(markdown, pulldown_cmark::Options::ENABLE_TABLES);
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser);
+    html
+}
+
+fn render_comrak(markdown: &str) -> String {
+    let mut options = comrak::Options::default();
+    options.extension.table = true;
+    options.render.unsafe_ = true;
+    comrak::markdown_to_html(markdown, &options)
+}
+
+fn render(markdown: &str) -> Outputs {
+    let md2html =
+        panic::catch_unwind(|| MarkdownParser::with_options(markdown, md2html_options()).parse())
+            .unwrap_or_else(|_| panic!("md2html panicked on {markdown:?}"));
+
+    Outputs {
+        md2html,
+        pulldown: render_pulldown(markdown),
+        comrak: render_comrak(markdown),
+    }
+}
+
+/// Collapses whitespace runs to one space and drops whitespace between tags.
+fn normalize(html: &str) -> String {
+    let mut normalized = String::with_capacity(html.len());
+    for word in html.split_whitespace() {
+        let between_tags = normalized.ends_with('>') && word.starts_with('<');
+        if !normalized.is_empty() && !between_tags {
+            normalized.push(' ');
+        }
+        normalized.push_str(word);
+    }
+    normalized
+}
+
+/// Splits a document into its top-level blocks at blank lines, keeping fenced
+/// code blocks and indented continuations (nested list content, indented
+/// code) with the block they belong to.
+fn split_blocks(document: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current = String::new();
+    let mut fence: Option<&str> = None;
+    let mut blank_before = false;
+
+    for line in document.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+        } else if trimmed.is_empty() {
+            blank_before = true;
+            continue;
+        }
+
+        let continues = line.starts_with(' ') || line.starts_with('\t');
+        if blank_before && !continues && !current.is_empty() {
+            blocks.push(std::mem::take(&mut current));
+        } else if blank_before {
+            current.push('\n');
+        }
+        blank_before = false;
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+/// Names the block construct a top-level block starts with.
+fn classify(block: &str) -> &'static str {
+    let first = block.lines().next().unwrap_or("");
+    let trimmed = first.trim_start();
+    let second = block.lines().nth(1).unwrap_or("").trim();
+    let is_rule = |line: &str| {
+        let chars: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+        chars.len() >= 3
+            && ["-", "*", "_"]
+                .iter()
+                .any(|c| chars.chars().all(|x| x.to_string() == *c))
+    };
+
+    if first.starts_with("    ") || first.starts_with('\t') {
+        "indented code"
+    } else if trimmed.starts_with('#') {
+        "ATX heading"
+    } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+        "fenced code"
+    } else if is_rule(trimmed) {
+        "thematic break"
+    } else if trimmed.starts_with('>') {
+        "block quote"
+    } else if trimmed.starts_with("- ") || trimmed.starts_with("* ") || trimmed.starts_with("+ ") {
+        "bullet list"
+    } else if trimmed
+        .split_once(['.', ')'])
+        .is_some_and(|(n, _)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    {
+        "ordered list"
+    } else if trimmed.contains('|') && second.contains('-') && second.contains('|') {
+        "table"
+    } else if trimmed.starts_with('<') {
+        "HTML"
+    } else if !second.is_empty() && (second.bytes().all(|b| b == b'=') || is_rule(second)) {
+        "setext heading"
+    } else {
+        "paragraph"
+    }
+}
+
+fn sample_cases() -> Vec<Case> {
+    let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("benchmark/samples");
+    let mut paths: Vec<PathBuf> = fs::read_dir(&samples)
+        .expect("benchmark/samples should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .collect();
+    paths.sort();
+
+    let mut cases = Vec::new();
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let document = fs::read_to_string(&path).unwrap();
+        for (i, block) in split_blocks(&document).into_iter().enumerate() {
+            cases.push(Case {
+                construct: classify(&block).to_string(),
+                source: format!("{name}, block {}", i + 1),
+                markdown: block,
+            });
+        }
+        cases.push(Case {
+            construct: "whole document".to_string(),
+            source: name,
+            markdown: document,
+        });
+    }
+    cases
+}
+
+/// Snippets exercising each construct, built by combining variations.
+fn generated_cases() -> Vec<Case> {
+    let mut generated: Vec<(&str, Vec<String>)> = Vec::new();
+    let words = ["foo", "foo bar", " foo ", "foo*bar", "foo_bar", "(foo)"];
+
+    let mut emphasis = Vec::new();
+    for delim in ["*", "_", "**", "__", "***"] {
+        for word in words {
+            emphasis.push(format!("a {delim}{word}{delim} b\n"));
+            emphasis.push(format!("a{delim}{word}{delim}b\n"));
+        }
+        emphasis.push(format!("{delim}unclosed\n"));
+        emphasis.push(format!(
+            "{delim}nested {delim}{delim}deep{delim}{delim} x{delim}\n"
+        ));
+    }
+    generated.push(("emphasis", emphasis));
+
+    let mut code_spans = Vec::new();
+    for ticks in ["`", "``"] {
+        for content in ["code", " code ", "a ` b", "<tag> & x", "multi\nline"] {
+            code_spans.push(format!("x {ticks}{content}{ticks} y\n"));
+        }
+    }
+    code_spans.push("`unclosed\n".to_string());
+    generated.push(("code span", code_spans));
+
+    let mut links = Vec::new();
+    for dest in [
+        "/url",
+        "https://example.com/a?b=c&d",
+        "<with space>",
+        "(parens)",
+    ] {
+        for title in ["", " \"title\"", " 'title'"] {
+            links.push(format!("[text]({dest}{title})\n"));
+        }
+    }
+    links.push("[*emphasised* `code`](/url)\n".to_string());
+    links.push("[ref]\n\n[ref]: /url\n".to_string());
+    links.push("<https://example.com>\n".to_string());
+    links.push("![alt *text*](/img.png \"title\")\n".to_string());
+    generated.push(("link", links));
+
+    let mut headings = Vec::new();
+    for level in 1..=7 {
+        let hashes = "#".repeat(level);
+        headings.push(format!("{hashes} Heading *{level}*\n"));
+        headings.push(format!("{hashes}no space\n"));
+    }
+    headings.push("# closed #\n".to_string());
+    generated.push(("ATX heading", headings));
+    generated.push((
+        "setext heading",
+        vec![
+            "Setext *one*\n======\n".to_string(),
+            "Setext\ntwo lines\n---\n".to_string(),
+        ],
+    ));
+
+    let list_variants = |markers: &[&str]| {
+        let mut lists = Vec::new();
+        for marker in markers {
+            lists.push(format!("{marker} one\n{marker} two\n"));
+            lists.push(format!("{marker} one\n\n{marker} two\n"));
+            lists.push(format!("{marker} one\n   {marker} nested\n{marker} two\n"));
+            lists.push(format!("{marker} one\n\n   second paragraph\n"));
+        }
+        lists
+    };
+    let mut bullet_lists = list_variants(&["-", "*", "+"]);
+    bullet_lists.push("- a\n+ b\n".to_string());
+    bullet_lists.push("- [ ] task\n- [x] done\n".to_string());
+    generated.push(("bullet list", bullet_lists));
+    generated.push(("ordered list", list_variants(&["1.", "3)"])));
+
+    let mut quotes = Vec::new();
+    for body in ["quote", "# heading", "- item", "> nested", "```\ncode\n```"] {
+        let quoted: String = body.lines().map(|line| format!("> {line}\n")).collect();
+        quotes.push(quoted);
+    }
+    quotes.push("> lazy\ncontinuation\n".to_string());
+    generated.push(("block quote", quotes));
+
+    let mut code_blocks = Vec::new();
+    for fence in ["```", "~~~", "````"] {
+        for info in ["", "rust", "rust ignore"] {
+            code_blocks.push(format!(
+                "{fence}{info}\nfn main() {{}}\n\n<x> & y\n{fence}\n"
+            ));
+        }
+    }
+    code_blocks.push("```\nunclosed\n".to_string());
+    generated.push(("fenced code", code_blocks));
+    generated.push((
+        "indented code",
+        vec!["    indented\n      code\n\n    more\n".to_string()],
+    ));
+
+    let mut tables = Vec::new();
+    for delimiter in ["---", ":--", "--:", ":-:"] {
+        tables.push(format!("| a | b |\n|{delimiter}|{delimiter}|\n| 1 | 2 |\n"));
+    }
+    tables.push("a | b\n--|--\n1 | 2 | 3\n".to_string());
+    tables.push("| a \\| b |\n|---|\n| `c` |\n".to_string());
+    generated.push(("table", tables));
+
+    let mut inline = Vec::new();
+    for text in [
+        "line one\nline two",
+        "hard  \nbreak",
+        "back\\\nslash",
+        "\\*escaped\\*",
+        "&amp; &copy; &#35;",
+        "a < b > c & d \"e\" 'f'",
+        "<span>inline</span> html",
+        "<!-- comment -->",
+    ] {
+        inline.push(format!("{text}\n"));
+    }
+    generated.push(("inline text", inline));
+
+    let mut breaks = Vec::new();
+    for rule in ["---", "***", "___", "- - -", " * * *"] {
+        breaks.push(format!("before\n\n{rule}\n\nafter\n"));
+    }
+    generated.push(("thematic break", breaks));
+
+    generated
+        .into_iter()
+        .flat_map(|(construct, snippets)| {
+            snippets
+                .into_iter()
+                .enumerate()
+                .map(move |(i, markdown)| Case {
+                    construct: construct.to_string(),
+                    source: format!("generated {construct} #{}", i + 1),
+                    markdown,
+                })
+        })
+        .collect()
+}
+
+#[test]
+fn differential() {
+    let cases: Vec<Case> = sample_cases()
+        .into_iter()
+        .chain(generated_cases())
+        .collect();
+
+    let mut tallies: BTreeMap<&str, Tally> = BTreeMap::new();
+    let mut details = String::new();
+
+    for case in &cases {
+        let outputs = render(&case.markdown);
+        let ours = normalize(&outputs.md2html);
+        let differs_pulldown = ours != normalize(&outputs.pulldown);
+        let differs_comrak = ours != normalize(&outputs.comrak);
+
+        let tally = tallies.entry(&case.construct).or_default();
+        tally.cases += 1;
+        tally.pulldown += usize::from(differs_pulldown);
+        tally.comrak += usize::from(differs_comrak);
+        tally.both += usize::from(differs_pulldown && differs_comrak);
+
+        if differs_pulldown || differs_comrak {
+            writeln!(details, "## {} ({})\n", case.construct, case.source).unwrap();
+            for (label, text) in [
+                ("input", &case.markdown),
+                ("md2html", &outputs.md2html),
+                ("pulldown-cmark", &outputs.pulldown),
+                ("comrak", &outputs.comrak),
+            ] {
+                // Fence the text with more backticks than it contains in a row
+                let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                let fence = "`".repeat(longest.max(2) + 1);
+                writeln!(details, "{label}:\n{fence}\n{}\n{fence}\n", text.trim_end()).unwrap();
+            }
+        }
+    }
+
+    let mut summary = format!(
+        "{:<16} {:>6} {:>10} {:>8} {:>6}\n",
+        "construct", "cases", "≠pulldown", "≠comrak", "≠both"
+    );
+    for (construct, tally) in &tallies {
+        writeln!(
+            summary,
+            "{construct:<16} {:>6} {:>10} {:>8} {:>6}",
+            tally.cases, tally.pulldown, tally.comrak, tally.both
+        )
+        .unwrap();
+    }
+    println!("{summary}");
+
+    let report = Path::new(env!("CARGO_TARGET_TMPDIR")).join("differential-report.md");
+    fs::write(
+        &report,
+        format!("# md2html differential report\n\n```\n{summary}```\n\n{details}"),
+    )
+    .unwrap();
+    println!("full report: {}", report.display());
+}